@@ -0,0 +1,211 @@
+use crate::grid::Grid;
+use crate::inventory::Inventory;
+use crate::storage::{StoredItem, StoredItemType};
+use std::cmp::min;
+use std::collections::BTreeSet;
+use serde::Serialize;
+
+pub const UPGRADE_SLOTS: usize = 4;
+pub const BASE_FILTER_SLOTS: usize = 1;
+pub const FILTER_SLOTS_PER_CAPACITY_CARD: usize = 4;
+/// Items moved per operation with 0 to 4 acceleration cards
+pub const ITEMS_PER_OPERATION: [i32; UPGRADE_SLOTS + 1] = [1, 8, 32, 64, 96];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum UpgradeCard {
+    /// Raises the items moved per operation
+    Acceleration,
+    /// Unlocks more filter slots
+    Capacity
+}
+
+/// Filter and upgrades shared by import and export buses
+#[derive(Debug, Clone, Serialize)]
+pub struct BusConfig<'a, T: StoredItemType> {
+    /// Entries beyond the unlocked filter slots are kept but ignored
+    pub filter: Vec<&'a T>,
+    upgrades: Vec<UpgradeCard>,
+}
+
+impl<'a, T: StoredItemType> Default for BusConfig<'a, T> {
+    fn default() -> Self {
+        BusConfig {
+            filter: vec![],
+            upgrades: vec![]
+        }
+    }
+}
+
+impl<'a, T: StoredItemType> BusConfig<'a, T> {
+    pub fn with_filter(filter: Vec<&'a T>) -> Self {
+        BusConfig {
+            filter,
+            upgrades: vec![]
+        }
+    }
+
+    pub fn upgrades(&self) -> &[UpgradeCard] {
+        &self.upgrades
+    }
+
+    /// Hands the card back when every upgrade slot is taken
+    pub fn install(&mut self, card: UpgradeCard) -> Result<(), UpgradeCard> {
+        if self.upgrades.len() >= UPGRADE_SLOTS {
+            return Err(card);
+        }
+        self.upgrades.push(card);
+        Ok(())
+    }
+
+    pub fn uninstall(&mut self, card: UpgradeCard) -> Option<UpgradeCard> {
+        let index = self.upgrades.iter().position(|x| *x == card)?;
+        Some(self.upgrades.remove(index))
+    }
+
+    fn installed(&self, card: UpgradeCard) -> usize {
+        self.upgrades.iter().filter(|x| **x == card).count()
+    }
+
+    pub fn items_per_operation(&self) -> i32 {
+        ITEMS_PER_OPERATION[self.installed(UpgradeCard::Acceleration)]
+    }
+
+    pub fn filter_slots(&self) -> usize {
+        BASE_FILTER_SLOTS + self.installed(UpgradeCard::Capacity) * FILTER_SLOTS_PER_CAPACITY_CARD
+    }
+
+    pub fn active_filter(&self) -> &[&'a T] {
+        &self.filter[..min(self.filter.len(), self.filter_slots())]
+    }
+}
+
+/// Adds `count` of `key` to the stacks a bus is holding
+fn hold<'a, T: StoredItemType>(held: &mut Vec<StoredItem<'a, T>>, key: &'a T, count: i32) {
+    if count <= 0 {
+        return;
+    }
+    match held.iter_mut().find(|x| x.item == key) {
+        Some(stack) => stack.count += count,
+        None => held.push(StoredItem::new(key, count))
+    }
+}
+
+/// Pulls items out of an adjacent inventory into the grid
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportBus<'a, T: StoredItemType> {
+    pub config: BusConfig<'a, T>,
+    /// Extracted items neither the grid nor the inventory took back, delivered before anything else
+    pub held: Vec<StoredItem<'a, T>>,
+}
+
+impl<'a, T: StoredItemType> ImportBus<'a, T> {
+    pub fn new(config: BusConfig<'a, T>) -> Self {
+        ImportBus {
+            config,
+            held: vec![]
+        }
+    }
+
+    /// Whether the bus picks up `item`, an empty filter picks up anything
+    pub fn accepts(&self, item: &T) -> bool {
+        let filter = self.config.active_filter();
+        filter.is_empty() || filter.contains(&item)
+    }
+
+    /// Runs one operation, returns how many items were moved
+    pub fn tick(&mut self, grid: &mut Grid<'a, T>, inventory: &mut Inventory<'a, T>) -> i32 {
+        self.transfer(grid, inventory, self.config.items_per_operation())
+    }
+
+    /// Moves up to `limit` items, held items first. Only what the grid reports it can store leaves the inventory.
+    pub fn transfer(&mut self, grid: &mut Grid<'a, T>, inventory: &mut Inventory<'a, T>, limit: i32) -> i32 {
+        let mut budget = limit;
+        let mut moved = 0;
+        for stack in std::mem::take(&mut self.held) {
+            let count = min(stack.count, budget);
+            let left = if count > 0 { grid.insert(StoredItem::new(stack.item, count)) } else { 0 };
+            moved += count - left;
+            budget -= count - left;
+            hold(&mut self.held, stack.item, stack.count - count + left);
+        }
+        let mut keys: Vec<&'a T> = inventory.stacks().map(|x| x.item).filter(|x| self.accepts(x)).collect();
+        let mut seen = BTreeSet::new();
+        keys.retain(|x| seen.insert(*x));
+        for key in keys {
+            if budget == 0 {
+                break;
+            }
+            let available = inventory.extract(key, budget, true);
+            let accepted = available - grid.simulate_insert(&StoredItem::new(key, available));
+            if accepted <= 0 {
+                continue;
+            }
+            let extracted = inventory.extract(key, accepted, false);
+            let left = grid.insert(StoredItem::new(key, extracted));
+            if left > 0 {
+                // The grid changed its mind, what came out of the inventory goes back in
+                let kept = inventory.insert(StoredItem::new(key, left), false);
+                hold(&mut self.held, key, kept);
+            }
+            moved += extracted - left;
+            budget -= extracted - left;
+        }
+        moved
+    }
+}
+
+/// Pushes filtered items from the grid into an adjacent inventory
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportBus<'a, T: StoredItemType> {
+    pub config: BusConfig<'a, T>,
+    /// Taken items neither the inventory nor the grid took back, delivered before anything else
+    pub held: Vec<StoredItem<'a, T>>,
+}
+
+impl<'a, T: StoredItemType> ExportBus<'a, T> {
+    pub fn new(config: BusConfig<'a, T>) -> Self {
+        ExportBus {
+            config,
+            held: vec![]
+        }
+    }
+
+    /// Runs one operation, returns how many items were moved
+    pub fn tick(&mut self, grid: &mut Grid<'a, T>, inventory: &mut Inventory<'a, T>) -> i32 {
+        self.transfer(grid, inventory, self.config.items_per_operation())
+    }
+
+    /// Moves up to `limit` items, held items first. Filter entries are served in order, only what
+    /// the inventory reports it can hold leaves the grid. An empty filter exports nothing.
+    pub fn transfer(&mut self, grid: &mut Grid<'a, T>, inventory: &mut Inventory<'a, T>, limit: i32) -> i32 {
+        let mut budget = limit;
+        let mut moved = 0;
+        for stack in std::mem::take(&mut self.held) {
+            let count = min(stack.count, budget);
+            let left = if count > 0 { inventory.insert(StoredItem::new(stack.item, count), false) } else { 0 };
+            moved += count - left;
+            budget -= count - left;
+            hold(&mut self.held, stack.item, stack.count - count + left);
+        }
+        let keys: Vec<&'a T> = self.config.active_filter().to_vec();
+        for key in keys {
+            if budget == 0 {
+                break;
+            }
+            let available = grid.simulate_take(&StoredItem::new(key, budget));
+            let fits = available - inventory.insert(StoredItem::new(key, available), true);
+            if fits <= 0 {
+                continue;
+            }
+            let taken = grid.take(StoredItem::new(key, fits));
+            let left = inventory.insert(StoredItem::new(key, taken), false);
+            if left > 0 {
+                let kept = grid.insert(StoredItem::new(key, left));
+                hold(&mut self.held, key, kept);
+            }
+            moved += taken - left;
+            budget -= taken - left;
+        }
+        moved
+    }
+}
@@ -0,0 +1,104 @@
+use crate::topology::{CableKind, DeviceKind, NodeId, Topology};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use serde::Serialize;
+
+/// Channels supplied through each face of a controller
+pub const CONTROLLER_FACE_CHANNELS: u32 = 32;
+pub const NORMAL_CABLE_CHANNELS: u32 = 8;
+pub const DENSE_CABLE_CHANNELS: u32 = 32;
+/// Devices a network without a controller supports, one more and the whole network fails
+pub const AD_HOC_CHANNELS: u32 = 8;
+
+impl DeviceKind {
+    pub fn requires_channel(&self) -> bool {
+        !matches!(self, DeviceKind::Controller | DeviceKind::Cable(_))
+    }
+
+    /// Channels a cable can carry
+    pub fn channel_capacity(&self) -> Option<u32> {
+        match self {
+            DeviceKind::Cable(CableKind::Normal) => Some(NORMAL_CABLE_CHANNELS),
+            DeviceKind::Cable(CableKind::Dense) => Some(DENSE_CABLE_CHANNELS),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct ChannelAllocation {
+    /// Devices holding a channel
+    pub online: BTreeSet<NodeId>,
+    /// Devices that need a channel but did not get one
+    pub offline: BTreeSet<NodeId>,
+    /// Channels carried by every cable in use
+    pub usage: BTreeMap<NodeId, u32>,
+}
+
+/// Assigns channels network by network. Devices are served closest first along the shortest
+/// cable path to a controller, a device whose path has a saturated cable or controller face goes without.
+pub fn allocate(topology: &Topology) -> ChannelAllocation {
+    let mut allocation = ChannelAllocation::default();
+    for grid_id in topology.networks.keys() {
+        let nodes: Vec<NodeId> = topology.membership.iter().filter(|x| x.1 == grid_id).map(|x| *x.0).collect();
+        let devices: Vec<NodeId> = nodes.iter().copied().filter(|x| topology.nodes[x].kind.requires_channel()).collect();
+        let controllers: Vec<NodeId> = nodes.iter().copied().filter(|x| topology.nodes[x].kind == DeviceKind::Controller).collect();
+
+        if controllers.is_empty() {
+            if devices.len() as u32 <= AD_HOC_CHANNELS {
+                allocation.online.extend(devices);
+            } else {
+                allocation.offline.extend(devices);
+            }
+            continue;
+        }
+
+        // Shortest path tree from the controllers, channels only travel through cables
+        let mut parents = BTreeMap::new();
+        let mut visited: BTreeSet<NodeId> = controllers.iter().copied().collect();
+        let mut queue: VecDeque<NodeId> = controllers.iter().copied().collect();
+        let mut reached = vec![];
+        while let Some(id) = queue.pop_front() {
+            for next in topology.neighbours(id) {
+                if !visited.insert(next) {
+                    continue;
+                }
+                let kind = topology.nodes[&next].kind;
+                if kind.channel_capacity().is_some() {
+                    parents.insert(next, id);
+                    queue.push_back(next);
+                } else if kind.requires_channel() {
+                    parents.insert(next, id);
+                    reached.push(next);
+                }
+            }
+        }
+
+        let mut face_usage: BTreeMap<(NodeId, NodeId), u32> = BTreeMap::new();
+        for device in reached.iter() {
+            let mut cables = vec![];
+            let mut current = *device;
+            let mut parent = parents[&current];
+            while topology.nodes[&parent].kind != DeviceKind::Controller {
+                cables.push(parent);
+                current = parent;
+                parent = parents[&current];
+            }
+            let face = (parent, current);
+            let fits = face_usage.get(&face).copied().unwrap_or(0) < CONTROLLER_FACE_CHANNELS
+                && cables.iter().all(|x| {
+                    allocation.usage.get(x).copied().unwrap_or(0) < topology.nodes[x].kind.channel_capacity().unwrap()
+                });
+            if fits {
+                *face_usage.entry(face).or_default() += 1;
+                for cable in cables {
+                    *allocation.usage.entry(cable).or_default() += 1;
+                }
+                allocation.online.insert(*device);
+            }
+        }
+        // Everything not served, including devices only reachable through other devices
+        let unserved: Vec<NodeId> = devices.into_iter().filter(|x| !allocation.online.contains(x)).collect();
+        allocation.offline.extend(unserved);
+    }
+    allocation
+}
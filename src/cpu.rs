@@ -0,0 +1,375 @@
+use crate::crafting::{CraftingPlan, Pattern, PatternBook, PatternId, PatternKind};
+use crate::energy::ENERGY_PER_CRAFT;
+use crate::grid::Grid;
+use crate::machine::PatternProvider;
+use crate::storage::{StoredItem, StoredItemType};
+use std::cmp::min;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::cell::RefCell;
+use serde::Serialize;
+
+/// Bytes of the crafting storage blocks a CPU can be built from
+pub const CRAFTING_STORAGE_1K: i32 = 1024;
+pub const CRAFTING_STORAGE_4K: i32 = 4096;
+pub const CRAFTING_STORAGE_16K: i32 = 16384;
+pub const CRAFTING_STORAGE_64K: i32 = 65536;
+/// Ticks between dispatching a crafting pattern and its outputs arriving, processing takes as long as the machine
+pub const CRAFT_TICKS: u64 = 1;
+
+impl<'a, T: StoredItemType> CraftingPlan<'a, T> {
+    /// CPU storage the job occupies: every item pulled from storage plus every item crafted
+    pub fn bytes(&self, patterns: &PatternBook<'a, T>) -> i32 {
+        let used = self.used.values().fold(0, |acc: i32, x| acc.saturating_add(*x));
+        let crafted = self.steps.iter()
+            .filter_map(|step| patterns.get(step.pattern).map(|x| x.outputs.iter().fold(0, |acc: i32, x| acc.saturating_add(x.count)).saturating_mul(step.crafts)))
+            .fold(0, |acc: i32, x| acc.saturating_add(x));
+        used.saturating_add(crafted)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum CpuError<'a, T: StoredItemType> {
+    /// Still working on another job
+    Busy,
+    TooLarge { required: i32, available: i32 },
+    /// Storage changed since planning, nothing was reserved. Ingredients that no longer fit
+    /// back into storage stay with the CPU, which returns them like a cancelled job.
+    Missing(Vec<StoredItem<'a, T>>),
+    /// The plan refers to a pattern that is not in the book it was submitted with
+    UnknownPattern(PatternId)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum JobState {
+    Running,
+    /// All crafts are done, waiting for room in storage to hand back the results
+    Delivering,
+    Done,
+    /// Cancelled, waiting for room in storage to hand back what the CPU still holds
+    Cancelling,
+    Cancelled
+}
+
+impl JobState {
+    /// Whether the CPU has nothing left to do for the job
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobState::Done | JobState::Cancelled)
+    }
+}
+
+/// Where the items of a cancelled job went
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CancelReport<'a, T: StoredItemType> {
+    pub returned: Vec<StoredItem<'a, T>>,
+    /// Did not fit into storage, the CPU keeps them and retries every tick
+    pub not_returned: Vec<StoredItem<'a, T>>,
+}
+
+/// Snapshot of a job for operators
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobStatus<'a, T: StoredItemType> {
+    pub state: JobState,
+    pub request: StoredItem<'a, T>,
+    pub crafts_done: i32,
+    pub crafts_total: i32,
+    pub started_at: u64,
+    /// Tick the job is expected to be done by, assuming ingredients keep flowing
+    pub estimated_completion: u64,
+}
+
+impl<'a, T: StoredItemType> JobStatus<'a, T> {
+    /// Fraction of crafts done, 1.0 for jobs served from storage alone
+    pub fn progress(&self) -> f64 {
+        if self.crafts_total == 0 {
+            return 1.0;
+        }
+        self.crafts_done as f64 / self.crafts_total as f64
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct InFlight {
+    step: usize,
+    crafts: i32,
+    done_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CraftingJob<'a, T: StoredItemType> {
+    pub plan: CraftingPlan<'a, T>,
+    /// Patterns of `plan.steps` with the planned substitutions, copied so the job does not depend on the book staying the same
+    patterns: Vec<Pattern<'a, T>>,
+    /// Reserved ingredients and crafted items held by the CPU
+    pub holding: BTreeMap<&'a T, i32>,
+    /// Crafts not dispatched yet, per step
+    pending: Vec<i32>,
+    in_flight: Vec<InFlight>,
+    /// Processing runs pushed into a machine, as step and provider index
+    in_machines: Vec<(usize, usize)>,
+    state: JobState,
+    started_at: u64,
+    crafts_done: i32,
+}
+
+impl<'a, T: StoredItemType> CraftingJob<'a, T> {
+    pub fn state(&self) -> JobState {
+        self.state
+    }
+
+    fn crafts_total(&self) -> i32 {
+        self.plan.steps.iter().fold(0, |acc, x| acc.saturating_add(x.crafts))
+    }
+
+    fn can_dispatch(&self, step: usize) -> bool {
+        self.patterns[step].inputs.iter().all(|x| self.holding.get(x.item).copied().unwrap_or(0) >= x.count)
+    }
+
+    fn add(&mut self, item: &'a T, count: i32) {
+        let held = self.holding.entry(item).or_default();
+        *held += count;
+        if *held == 0 {
+            self.holding.remove(item);
+        }
+    }
+
+    /// Inserts everything held into `grid`. Returns what went in and what is still held.
+    fn hand_back(&mut self, grid: &mut Grid<'a, T>) -> (Vec<StoredItem<'a, T>>, Vec<StoredItem<'a, T>>) {
+        let items: Vec<StoredItem<'a, T>> = self.holding.iter().map(|(item, count)| StoredItem::new(*item, *count)).collect();
+        let left = grid.insert_many(items.clone());
+        let mut returned = vec![];
+        let mut kept = vec![];
+        for (item, left) in items.into_iter().zip(left) {
+            self.add(item.item, left - item.count);
+            if left < item.count {
+                returned.push(StoredItem::new(item.item, item.count - left));
+            }
+            if left > 0 {
+                kept.push(StoredItem::new(item.item, left));
+            }
+        }
+        (returned, kept)
+    }
+}
+
+/// Crafting CPU cluster, runs one job at a time
+#[derive(Debug, Clone, Serialize)]
+pub struct CraftingCpu<'a, T: StoredItemType> {
+    pub storage_bytes: i32,
+    pub co_processors: u32,
+    job: Option<CraftingJob<'a, T>>,
+    /// Where processing patterns are sent, shared with the other CPUs of the network
+    #[serde(skip)]
+    providers: Vec<Rc<RefCell<PatternProvider<'a, T>>>>,
+}
+
+impl<'a, T: StoredItemType> CraftingCpu<'a, T> {
+    pub fn new(storage_bytes: i32, co_processors: u32) -> Self {
+        CraftingCpu {
+            storage_bytes,
+            co_processors,
+            job: None,
+            providers: vec![]
+        }
+    }
+
+    pub fn add_provider(&mut self, provider: Rc<RefCell<PatternProvider<'a, T>>>) {
+        self.providers.push(provider);
+    }
+
+    /// Ticks one run of a step takes
+    fn step_ticks(&self, job: &CraftingJob<'a, T>, step: usize) -> u64 {
+        if job.patterns[step].kind == PatternKind::Crafting {
+            return CRAFT_TICKS;
+        }
+        let pattern = job.plan.steps[step].pattern;
+        self.providers.iter()
+            .find(|x| x.borrow().provides(pattern))
+            .map_or(CRAFT_TICKS, |x| x.borrow().machine.borrow().duration())
+    }
+
+    /// Crafts dispatched per tick
+    pub fn parallelism(&self) -> i32 {
+        1 + self.co_processors as i32
+    }
+
+    pub fn job(&self) -> Option<&CraftingJob<'a, T>> {
+        self.job.as_ref()
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.job.as_ref().is_some_and(|x| !x.state.is_finished())
+    }
+
+    /// Reserves everything the plan takes out of `grid` and starts the job.
+    /// Either every ingredient is reserved or none is.
+    pub fn submit(&mut self, patterns: &PatternBook<'a, T>, plan: CraftingPlan<'a, T>, grid: &mut Grid<'a, T>, now: u64) -> Result<(), CpuError<'a, T>> {
+        if self.is_busy() {
+            return Err(CpuError::Busy);
+        }
+        let required = plan.bytes(patterns);
+        if required > self.storage_bytes {
+            return Err(CpuError::TooLarge { required, available: self.storage_bytes });
+        }
+        let mut job_patterns = vec![];
+        for (step, x) in plan.steps.iter().enumerate() {
+            job_patterns.push(plan.pattern(patterns, step).ok_or(CpuError::UnknownPattern(x.pattern))?);
+        }
+        let mut holding = BTreeMap::new();
+        let mut missing = vec![];
+        for (item, count) in plan.used.iter() {
+            let taken = grid.take(StoredItem::new(*item, *count));
+            if taken > 0 {
+                holding.insert(*item, taken);
+            }
+            if taken < *count {
+                missing.push(StoredItem::new(*item, count - taken));
+            }
+        }
+        let mut job = CraftingJob {
+            patterns: job_patterns,
+            pending: plan.steps.iter().map(|x| x.crafts).collect(),
+            plan,
+            holding,
+            in_flight: vec![],
+            in_machines: vec![],
+            state: JobState::Running,
+            started_at: now,
+            crafts_done: 0
+        };
+        if !missing.is_empty() {
+            job.pending.iter_mut().for_each(|x| *x = 0);
+            let (_, kept) = job.hand_back(grid);
+            if !kept.is_empty() {
+                job.state = JobState::Cancelling;
+                self.job = Some(job);
+            }
+            return Err(CpuError::Missing(missing));
+        }
+        self.job = Some(job);
+        Ok(())
+    }
+
+    /// Collects finished crafts, dispatches up to `budget` new ones within the parallelism limit
+    /// and hands the results back once everything is crafted. Returns the crafts dispatched.
+    pub fn tick(&mut self, grid: &mut Grid<'a, T>, now: u64, budget: i32) -> i32 {
+        let parallelism = self.parallelism();
+        let providers = &self.providers;
+        let job = match self.job.as_mut() {
+            Some(job) => job,
+            None => return 0
+        };
+        let (landed, in_flight): (Vec<InFlight>, Vec<InFlight>) = job.in_flight.drain(..).partition(|x| x.done_at <= now);
+        job.in_flight = in_flight;
+        for batch in landed {
+            for output in job.patterns[batch.step].outputs.clone() {
+                job.add(output.item, output.count * batch.crafts);
+            }
+            job.crafts_done += batch.crafts;
+        }
+        let mut in_machines = std::mem::take(&mut job.in_machines);
+        in_machines.retain(|(step, provider)| {
+            if !providers[*provider].borrow_mut().collect(&job.patterns[*step]) {
+                return true;
+            }
+            for output in job.patterns[*step].outputs.clone() {
+                job.add(output.item, output.count);
+            }
+            job.crafts_done += 1;
+            false
+        });
+        job.in_machines = in_machines;
+
+        let mut dispatched = 0;
+        let limit = min(parallelism, budget);
+        for step in 0..job.pending.len() {
+            while dispatched < limit && job.pending[step] > 0 && job.can_dispatch(step) {
+                let provider = match job.patterns[step].kind {
+                    PatternKind::Crafting => None,
+                    PatternKind::Processing => {
+                        let pattern = job.plan.steps[step].pattern;
+                        match providers.iter().position(|x| x.borrow().provides(pattern) && x.borrow().can_push(&job.patterns[step])) {
+                            Some(provider) => Some(provider),
+                            None => break
+                        }
+                    }
+                };
+                if grid.consume_energy(ENERGY_PER_CRAFT).is_err() {
+                    break;
+                }
+                for input in job.patterns[step].inputs.clone() {
+                    job.add(input.item, -input.count);
+                }
+                job.pending[step] -= 1;
+                dispatched += 1;
+                match provider {
+                    Some(provider) => {
+                        providers[provider].borrow_mut().push(&job.patterns[step]);
+                        job.in_machines.push((step, provider));
+                    }
+                    None => match job.in_flight.last_mut() {
+                        Some(batch) if batch.step == step && batch.done_at == now + CRAFT_TICKS => batch.crafts += 1,
+                        _ => job.in_flight.push(InFlight { step, crafts: 1, done_at: now + CRAFT_TICKS })
+                    }
+                }
+            }
+        }
+
+        if job.state == JobState::Running && job.in_flight.is_empty() && job.in_machines.is_empty() && job.pending.iter().all(|x| *x == 0) {
+            job.state = JobState::Delivering;
+        }
+        if matches!(job.state, JobState::Delivering | JobState::Cancelling) {
+            job.hand_back(grid);
+            if job.holding.is_empty() {
+                job.state = if job.state == JobState::Delivering { JobState::Done } else { JobState::Cancelled };
+            }
+        }
+        dispatched
+    }
+
+    /// Stops the running job and hands everything it holds back to `grid`: reserved ingredients,
+    /// crafted intermediates and the inputs of crafts still in flight. Runs already pushed into
+    /// a machine finish there, their providers return the outputs to the grid. `None` without a running job.
+    pub fn cancel(&mut self, grid: &mut Grid<'a, T>) -> Option<CancelReport<'a, T>> {
+        let job = self.job.as_mut().filter(|x| !x.state.is_finished())?;
+        for batch in std::mem::take(&mut job.in_flight) {
+            for input in job.patterns[batch.step].inputs.clone() {
+                job.add(input.item, input.count * batch.crafts);
+            }
+        }
+        for (step, provider) in std::mem::take(&mut job.in_machines) {
+            self.providers[provider].borrow_mut().release(&job.patterns[step]);
+        }
+        job.pending.iter_mut().for_each(|x| *x = 0);
+        let (returned, not_returned) = job.hand_back(grid);
+        job.state = if not_returned.is_empty() { JobState::Cancelled } else { JobState::Cancelling };
+        Some(CancelReport { returned, not_returned })
+    }
+
+    pub fn status(&self, now: u64) -> Option<JobStatus<'a, T>> {
+        let job = self.job.as_ref()?;
+        let crafts_total = job.crafts_total();
+        // Steps are assumed to run one after another, each in as few waves as the co-processors allow
+        let waves: u64 = job.pending.iter()
+            .enumerate()
+            .map(|(step, x)| (*x as u64).div_ceil(self.parallelism() as u64).saturating_mul(self.step_ticks(job, step)))
+            .fold(0, |acc: u64, x| acc.saturating_add(x));
+        let in_flight = job.in_flight.iter().map(|x| x.done_at.saturating_sub(now))
+            .chain(job.in_machines.iter().map(|x| self.step_ticks(job, x.0)))
+            .max()
+            .unwrap_or(0);
+        let remaining = match job.state {
+            JobState::Running => in_flight + waves,
+            JobState::Delivering | JobState::Cancelling => 1,
+            JobState::Done | JobState::Cancelled => 0
+        };
+        Some(JobStatus {
+            state: job.state,
+            request: job.plan.request.clone(),
+            crafts_done: job.crafts_done,
+            crafts_total,
+            started_at: job.started_at,
+            estimated_completion: now + remaining
+        })
+    }
+}
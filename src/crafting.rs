@@ -0,0 +1,375 @@
+use crate::grid::Grid;
+use crate::storage::{StoredItem, StoredItemType};
+use crate::tag::Tag;
+use std::cmp::{min, Reverse};
+use std::collections::{BTreeMap, BTreeSet};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum PatternKind {
+    /// Crafting table recipe, run by molecular assemblers
+    Crafting,
+    /// Anything an external machine does, e.g. smelting
+    Processing
+}
+
+/// One recipe: consumes `inputs` to produce `outputs` once
+#[derive(Debug, Clone, Serialize)]
+pub struct Pattern<'a, T: StoredItemType> {
+    pub kind: PatternKind,
+    pub inputs: Vec<StoredItem<'a, T>>,
+    /// The first output is what the pattern is for, the others are byproducts
+    pub outputs: Vec<StoredItem<'a, T>>,
+    /// Preferred over lower priorities when several patterns make the same item
+    pub priority: i32,
+    /// Inputs that may be any member of a tag instead of the listed key, by input index
+    pub tags: BTreeMap<usize, Tag<'a, T>>,
+}
+
+impl<'a, T: StoredItemType> Pattern<'a, T> {
+    pub fn new(kind: PatternKind, inputs: Vec<StoredItem<'a, T>>, outputs: Vec<StoredItem<'a, T>>) -> Self {
+        Pattern { kind, inputs, outputs, priority: 0, tags: BTreeMap::new() }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Lets the input at `input` be substituted by any member of `tag`
+    pub fn with_tag(mut self, input: usize, tag: Tag<'a, T>) -> Self {
+        self.tags.insert(input, tag);
+        self
+    }
+
+    /// Keys the input at `input` accepts, the listed one first
+    pub fn accepts(&self, input: usize) -> impl Iterator<Item = &'a T> + '_ {
+        let listed = self.inputs[input].item;
+        std::iter::once(listed).chain(self.tags.get(&input).into_iter().flat_map(|x| x.members()).filter(move |x| *x != listed))
+    }
+
+    pub fn crafting(inputs: Vec<StoredItem<'a, T>>, outputs: Vec<StoredItem<'a, T>>) -> Self {
+        Self::new(PatternKind::Crafting, inputs, outputs)
+    }
+
+    pub fn processing(inputs: Vec<StoredItem<'a, T>>, outputs: Vec<StoredItem<'a, T>>) -> Self {
+        Self::new(PatternKind::Processing, inputs, outputs)
+    }
+
+    /// Amount of `item` a single run produces
+    pub fn produces(&self, item: &T) -> i32 {
+        self.outputs.iter().filter(|x| x.item == item).map(|x| x.count).sum()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct PatternId(usize);
+
+/// Every pattern known to a network
+#[derive(Debug, Clone, Serialize)]
+pub struct PatternBook<'a, T: StoredItemType> {
+    patterns: Vec<Pattern<'a, T>>,
+}
+
+impl<'a, T: StoredItemType> Default for PatternBook<'a, T> {
+    fn default() -> Self {
+        PatternBook {
+            patterns: vec![]
+        }
+    }
+}
+
+impl<'a, T: StoredItemType> PatternBook<'a, T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, pattern: Pattern<'a, T>) -> PatternId {
+        self.patterns.push(pattern);
+        PatternId(self.patterns.len() - 1)
+    }
+
+    pub fn get(&self, id: PatternId) -> Option<&Pattern<'a, T>> {
+        self.patterns.get(id.0)
+    }
+
+    /// Patterns producing `item`, in the order they were added
+    pub fn producing<'b>(&'b self, item: &'b T) -> impl Iterator<Item = PatternId> + 'b {
+        self.patterns.iter().enumerate().filter(move |x| x.1.produces(item) > 0).map(|x| PatternId(x.0))
+    }
+}
+
+/// Runs of a single pattern
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CraftingStep {
+    pub pattern: PatternId,
+    pub crafts: i32,
+}
+
+/// Tag input of a step planned with another member than the listed key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Substitution<'a, T: StoredItemType> {
+    pub step: usize,
+    pub input: usize,
+    pub item: &'a T,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CraftingPlan<'a, T: StoredItemType> {
+    pub request: StoredItem<'a, T>,
+    /// Taken out of storage when the job starts
+    pub used: BTreeMap<&'a T, i32>,
+    /// In dependency order, a step only needs storage and the steps before it
+    pub steps: Vec<CraftingStep>,
+    /// Produced but not needed, returned to storage when the job is done
+    pub leftovers: BTreeMap<&'a T, i32>,
+    pub substitutions: Vec<Substitution<'a, T>>,
+}
+
+impl<'a, T: StoredItemType> CraftingPlan<'a, T> {
+    /// Pattern of a step with its tag inputs replaced by the planned members
+    pub fn pattern(&self, patterns: &PatternBook<'a, T>, step: usize) -> Option<Pattern<'a, T>> {
+        let mut pattern = patterns.get(self.steps.get(step)?.pattern)?.clone();
+        for substitution in self.substitutions.iter().filter(|x| x.step == step) {
+            pattern.inputs[substitution.input].item = substitution.item;
+        }
+        Some(pattern)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum CraftingError<'a, T: StoredItemType> {
+    /// Neither in storage nor craftable from what is, summed per key
+    Missing(Vec<StoredItem<'a, T>>),
+    /// Every route needs the request itself and storage has nothing to start from, e.g. ingot from
+    /// block from ingot. Holds the first loop found.
+    Cyclic(Vec<&'a T>),
+    /// Crafting the request needs more of this item than an `i32` can count
+    TooLarge(&'a T)
+}
+
+/// Runs of a pattern making `per_craft` per run needed for `count`, without overflowing
+fn crafts_for(count: i32, per_craft: i32) -> i32 {
+    count / per_craft + i32::from(count % per_craft != 0)
+}
+
+/// Walks the recipe tree for one request against a snapshot of storage.
+/// Alternative patterns are tried on a copy of the state, the first one that works is kept.
+#[derive(Clone)]
+struct Planner<'p, 'a, T: StoredItemType> {
+    patterns: &'p PatternBook<'a, T>,
+    /// Storage not claimed yet
+    stock: BTreeMap<&'a T, i32>,
+    used: BTreeMap<&'a T, i32>,
+    /// Surplus of earlier steps, consumed before storage
+    produced: BTreeMap<&'a T, i32>,
+    steps: Vec<CraftingStep>,
+    substitutions: Vec<Substitution<'a, T>>,
+    missing: BTreeMap<&'a T, i32>,
+    /// Missing only because every route to them loops
+    looping: BTreeSet<&'a T>,
+    /// Entries added to `missing` so far, tells a route that ran short from one that only looped
+    reported: usize,
+    /// Missing items without any pattern
+    base_missing: usize,
+    cycles: Vec<Vec<&'a T>>,
+    /// Items being crafted further up, asking for one of them again can only be served from storage
+    crafting: Vec<&'a T>,
+    /// First item whose amounts overflowed, planning stops there
+    too_large: Option<&'a T>,
+}
+
+impl<'p, 'a, T: StoredItemType> Planner<'p, 'a, T> {
+    fn claim(map: &mut BTreeMap<&'a T, i32>, item: &'a T, count: i32) -> i32 {
+        let available = map.get(item).copied().unwrap_or(0);
+        let claimed = min(available, count);
+        if claimed > 0 {
+            map.insert(item, available - claimed);
+        }
+        claimed
+    }
+
+    fn available(&self, item: &T) -> i32 {
+        self.stock.get(item).copied().unwrap_or(0).saturating_add(self.produced.get(item).copied().unwrap_or(0))
+    }
+
+    /// Key to use for `crafts` runs of the input at `input`: the most plentiful accepted key if it
+    /// covers them, otherwise one that can be crafted, otherwise still the most plentiful.
+    /// Ties go to the listed key.
+    fn choose(&self, pattern: &Pattern<'a, T>, input: usize, crafts: i32) -> &'a T {
+        let mut best = pattern.inputs[input].item;
+        for item in pattern.accepts(input) {
+            if self.available(item) > self.available(best) {
+                best = item;
+            }
+        }
+        if self.available(best) >= pattern.inputs[input].count.saturating_mul(crafts) {
+            return best;
+        }
+        pattern.accepts(input).find(|x| self.patterns.producing(x).next().is_some()).unwrap_or(best)
+    }
+
+    /// Patterns for `item`, highest priority first, then the ones with more ingredients at hand
+    fn ranked(&self, item: &'a T, count: i32) -> Vec<PatternId> {
+        let mut candidates: Vec<PatternId> = self.patterns.producing(item).collect();
+        candidates.sort_by_key(|id| {
+            let pattern = self.patterns.get(*id).unwrap();
+            let crafts = crafts_for(count, pattern.produces(item));
+            let at_hand = pattern.inputs.iter()
+                .enumerate()
+                .filter(|(i, x)| self.available(self.choose(pattern, *i, crafts)) >= x.count.saturating_mul(crafts))
+                .count();
+            (Reverse(pattern.priority), Reverse(at_hand))
+        });
+        candidates
+    }
+
+    /// Claims `count` of `item` from earlier steps, storage or new crafts. Returns false if something
+    /// is missing, the reason is recorded either way.
+    fn request(&mut self, item: &'a T, count: i32) -> bool {
+        let mut remaining = count - Self::claim(&mut self.produced, item, count);
+        let from_storage = Self::claim(&mut self.stock, item, remaining);
+        if from_storage > 0 {
+            *self.used.entry(item).or_default() += from_storage;
+            remaining -= from_storage;
+        }
+        if remaining == 0 {
+            return true;
+        }
+        if self.too_large.is_some() {
+            return false;
+        }
+        if let Some(start) = self.crafting.iter().position(|x| *x == item) {
+            let mut cycle = self.crafting[start..].to_vec();
+            cycle.push(item);
+            self.cycles.push(cycle);
+            return false;
+        }
+        let candidates = self.ranked(item, remaining);
+        if candidates.is_empty() {
+            let missing = self.missing.entry(item).or_default();
+            *missing = missing.saturating_add(remaining);
+            self.reported += 1;
+            self.base_missing += 1;
+            return false;
+        }
+        // Which failed route to report: one short of base items, then one using storage, then the first tried
+        let score = |attempt: &Self| (attempt.base_missing > self.base_missing, attempt.used != self.used);
+        let mut fallback: Option<Self> = None;
+        for id in candidates {
+            let mut attempt = self.clone();
+            if attempt.craft(id, item, remaining) {
+                *self = attempt;
+                return true;
+            }
+            if attempt.too_large.is_some() {
+                *self = attempt;
+                return false;
+            }
+            if fallback.as_ref().is_none_or(|x| score(&attempt) > score(x)) {
+                fallback = Some(attempt);
+            }
+        }
+        let fallback = fallback.unwrap();
+        if fallback.reported > self.reported {
+            *self = fallback;
+        } else {
+            // Every route loops, the item itself is what is missing
+            self.cycles = fallback.cycles;
+            let missing = self.missing.entry(item).or_default();
+            *missing = missing.saturating_add(remaining);
+            self.looping.insert(item);
+            self.reported += 1;
+        }
+        false
+    }
+
+    /// Plans enough runs of `id` to make `count` of `item`
+    fn craft(&mut self, id: PatternId, item: &'a T, count: i32) -> bool {
+        let pattern = self.patterns.get(id).unwrap();
+        let crafts = crafts_for(count, pattern.produces(item));
+        let inputs: Option<Vec<i32>> = pattern.inputs.iter().map(|x| x.count.checked_mul(crafts)).collect();
+        let inputs = match inputs {
+            Some(inputs) => inputs,
+            None => {
+                self.too_large = Some(item);
+                return false;
+            }
+        };
+        // Tag inputs are settled up front, each on the member most plentiful at this point
+        let chosen: Vec<&'a T> = (0..pattern.inputs.len()).map(|i| self.choose(pattern, i, crafts)).collect();
+        self.crafting.push(item);
+        // Keep going after a failure so every missing ingredient gets reported
+        let mut complete = true;
+        for (count, chosen) in inputs.into_iter().zip(chosen.iter()) {
+            complete &= self.request(chosen, count);
+        }
+        self.crafting.pop();
+        for output in pattern.outputs.iter() {
+            let produced = self.produced.entry(output.item).or_default();
+            match output.count.checked_mul(crafts).and_then(|x| x.checked_add(*produced)) {
+                Some(total) => *produced = total,
+                None => {
+                    self.too_large = Some(output.item);
+                    return false;
+                }
+            }
+        }
+        Self::claim(&mut self.produced, item, count);
+        for (input, chosen) in chosen.into_iter().enumerate() {
+            if chosen != pattern.inputs[input].item {
+                self.substitutions.push(Substitution { step: self.steps.len(), input, item: chosen });
+            }
+        }
+        self.steps.push(CraftingStep { pattern: id, crafts });
+        complete
+    }
+}
+
+impl<'a, T: StoredItemType> PatternBook<'a, T> {
+    /// Works out how to get `request` from what `grid` currently stores, crafting whatever is short.
+    /// Fails with every missing ingredient of the best route if it cannot be done, with a loop
+    /// if every route needs the request itself and storage has nothing to start from, or when
+    /// an amount on the way does not fit an `i32`.
+    pub fn plan(&self, grid: &Grid<'a, T>, request: StoredItem<'a, T>) -> Result<CraftingPlan<'a, T>, CraftingError<'a, T>> {
+        let stock = grid.stored_items_cache.iter().map(|(key, x)| (*key, x.count)).collect();
+        self.plan_with(stock, request)
+    }
+
+    /// Same as `plan` against an explicit storage snapshot
+    pub fn plan_with(&self, stock: BTreeMap<&'a T, i32>, request: StoredItem<'a, T>) -> Result<CraftingPlan<'a, T>, CraftingError<'a, T>> {
+        let mut planner = Planner {
+            patterns: self,
+            stock,
+            used: BTreeMap::new(),
+            produced: BTreeMap::new(),
+            steps: vec![],
+            substitutions: vec![],
+            missing: BTreeMap::new(),
+            looping: BTreeSet::new(),
+            reported: 0,
+            base_missing: 0,
+            cycles: vec![],
+            crafting: vec![],
+            too_large: None
+        };
+        if !planner.request(request.item, request.count) {
+            if let Some(item) = planner.too_large {
+                return Err(CraftingError::TooLarge(item));
+            }
+            // Nothing in storage gets the loop going
+            if planner.used.is_empty() && planner.missing.keys().all(|x| planner.looping.contains(x)) && !planner.cycles.is_empty() {
+                return Err(CraftingError::Cyclic(planner.cycles.swap_remove(0)));
+            }
+            return Err(CraftingError::Missing(planner.missing.into_iter().map(|(item, count)| StoredItem::new(item, count)).collect()));
+        }
+        planner.produced.retain(|_, x| *x > 0);
+        Ok(CraftingPlan {
+            request,
+            used: planner.used,
+            steps: planner.steps,
+            leftovers: planner.produced,
+            substitutions: planner.substitutions
+        })
+    }
+}
@@ -0,0 +1,133 @@
+use crate::tag::{TagEntry, TagError, TagName, TagRegistry};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+
+/// `data/<ns>/tags/<dir>/**.json`, newer game versions use the singular directory names
+const ITEM_TAG_DIRS: [&str; 2] = ["items", "item"];
+const FLUID_TAG_DIRS: [&str; 2] = ["fluids", "fluid"];
+
+#[derive(Deserialize)]
+struct TagFile {
+    #[serde(default)]
+    replace: bool,
+    values: Vec<TagFileValue>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TagFileValue {
+    Plain(String),
+    Entry {
+        id: String,
+        #[serde(default = "required_default")]
+        required: bool
+    }
+}
+
+fn required_default() -> bool {
+    true
+}
+
+#[derive(Debug)]
+pub enum DataPackError {
+    Io { path: PathBuf, error: io::Error },
+    Json { path: PathBuf, error: serde_json::Error },
+    /// Bad tag name, either the file's own or one it references
+    Tag { path: PathBuf, error: TagError }
+}
+
+/// Item and fluid tags of one or more data packs, item dumps add to them as well
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DataPackTags {
+    pub items: TagRegistry,
+    pub fluids: TagRegistry,
+}
+
+impl DataPackTags {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_dir(root: &Path) -> Result<Self, DataPackError> {
+        let mut tags = Self::new();
+        tags.load(root)?;
+        Ok(tags)
+    }
+
+    /// Loads the tags of the data pack at `root`, returns the number of tag files read.
+    /// Files with `replace` drop what earlier packs defined for the tag, others add to it,
+    /// so loading packs one after another layers them like the game does.
+    pub fn load(&mut self, root: &Path) -> Result<usize, DataPackError> {
+        let mut loaded = 0;
+        for (namespace, dir) in sorted_dirs(&root.join("data"))? {
+            let tags = dir.join("tags");
+            for (kind, registry) in [(&ITEM_TAG_DIRS, &mut self.items), (&FLUID_TAG_DIRS, &mut self.fluids)] {
+                for name in kind.iter() {
+                    let base = tags.join(name);
+                    if base.is_dir() {
+                        loaded += load_tags(&namespace, &base, &base, registry)?;
+                    }
+                }
+            }
+        }
+        Ok(loaded)
+    }
+}
+
+/// Subdirectories of `dir` by name, sorted so loading does not depend on the file system
+fn sorted_dirs(dir: &Path) -> Result<Vec<(String, PathBuf)>, DataPackError> {
+    let mut dirs: Vec<(String, PathBuf)> = read_dir(dir)?.into_iter()
+        .filter(|x| x.is_dir())
+        .filter_map(|x| Some((x.file_name()?.to_str()?.to_string(), x)))
+        .collect();
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn read_dir(dir: &Path) -> Result<Vec<PathBuf>, DataPackError> {
+    let io_error = |error| DataPackError::Io { path: dir.to_path_buf(), error };
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir).map_err(io_error)? {
+        paths.push(entry.map_err(io_error)?.path());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+fn load_tags(namespace: &str, base: &Path, dir: &Path, registry: &mut TagRegistry) -> Result<usize, DataPackError> {
+    let mut loaded = 0;
+    for path in read_dir(dir)? {
+        if path.is_dir() {
+            loaded += load_tags(namespace, base, &path, registry)?;
+            continue;
+        }
+        if path.extension().is_none_or(|x| x != "json") {
+            continue;
+        }
+        let relative: Vec<String> = path.strip_prefix(base).unwrap().with_extension("").components()
+            .map(|x| x.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let tag_error = |error| DataPackError::Tag { path: path.clone(), error };
+        let name = TagName::parse(&format!("{}:{}", namespace, relative.join("/"))).map_err(tag_error)?;
+        let text = fs::read_to_string(&path).map_err(|error| DataPackError::Io { path: path.clone(), error })?;
+        let file: TagFile = serde_json::from_str(&text).map_err(|error| DataPackError::Json { path: path.clone(), error })?;
+        let mut entries = vec![];
+        for value in file.values {
+            let entry = match value {
+                TagFileValue::Plain(id) => TagEntry::parse(&id),
+                TagFileValue::Entry { id, required: true } => TagEntry::parse(&id),
+                TagFileValue::Entry { id, required: false } => TagEntry::parse(&id).map(TagEntry::optional)
+            };
+            entries.push(entry.map_err(tag_error)?);
+        }
+        if file.replace {
+            registry.define(name, entries);
+        } else {
+            registry.extend(name, entries);
+        }
+        loaded += 1;
+    }
+    Ok(loaded)
+}
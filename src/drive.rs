@@ -0,0 +1,98 @@
+use crate::storage::{StorageCell, StoredItemType};
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_HOST_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Process-wide unique identifier of a cell host
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct HostId(usize);
+
+impl HostId {
+    fn next() -> Self {
+        HostId(NEXT_HOST_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub enum CellHostKind {
+    /// ME Drive
+    Drive,
+    /// ME Chest
+    Chest
+}
+
+impl CellHostKind {
+    pub fn slot_count(&self) -> usize {
+        match self {
+            CellHostKind::Drive => 10,
+            CellHostKind::Chest => 1
+        }
+    }
+}
+
+/// Block hosting storage cells, all hosted cells share its priority
+#[derive(Debug, Serialize)]
+pub struct CellHost<'a, T: StoredItemType> {
+    pub id: HostId,
+    pub kind: CellHostKind,
+    pub priority: i32,
+    pub powered: bool,
+    /// Cleared when the network cannot give the host a channel
+    pub has_channel: bool,
+    pub slots: Vec<Option<StorageCell<'a, T>>>,
+}
+
+impl<'a, T: StoredItemType> CellHost<'a, T> {
+    pub fn new(kind: CellHostKind) -> Self {
+        CellHost {
+            id: HostId::next(),
+            kind,
+            priority: 0,
+            powered: true,
+            has_channel: true,
+            slots: (0..kind.slot_count()).map(|_| None).collect()
+        }
+    }
+
+    pub fn drive() -> Self {
+        Self::new(CellHostKind::Drive)
+    }
+
+    pub fn chest() -> Self {
+        Self::new(CellHostKind::Chest)
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.powered && self.has_channel
+    }
+
+    /// Puts a cell into a free slot, giving it back if the slot is taken or does not exist
+    pub fn mount(&mut self, slot: usize, cell: StorageCell<'a, T>) -> Result<(), StorageCell<'a, T>> {
+        match self.slots.get_mut(slot) {
+            Some(x) if x.is_none() => {
+                *x = Some(cell);
+                Ok(())
+            }
+            _ => Err(cell)
+        }
+    }
+
+    pub fn eject(&mut self, slot: usize) -> Option<StorageCell<'a, T>> {
+        self.slots.get_mut(slot).and_then(|x| x.take())
+    }
+
+    pub fn free_slot(&self) -> Option<usize> {
+        self.slots.iter().position(|x| x.is_none())
+    }
+
+    /// Occupied slots with their cells
+    pub fn cells(&self) -> impl Iterator<Item = (usize, &StorageCell<'a, T>)> {
+        self.slots.iter().enumerate().filter_map(|(slot, cell)| cell.as_ref().map(|x| (slot, x)))
+    }
+}
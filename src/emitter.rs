@@ -0,0 +1,96 @@
+use crate::grid::Grid;
+use crate::storage::StoredItemType;
+use crate::watch::{StoredItemChange, WatchFilter, WatcherId};
+use std::sync::mpsc::Receiver;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum EmitterMode {
+    /// Emits while the amount is at or above the threshold
+    AboveOrEqual,
+    /// Emits while the amount is below the threshold
+    Below
+}
+
+impl EmitterMode {
+    pub fn evaluate(&self, amount: i32, threshold: i32) -> bool {
+        match self {
+            EmitterMode::AboveOrEqual => amount >= threshold,
+            EmitterMode::Below => amount < threshold
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum EmitterEdge {
+    Rising,
+    Falling
+}
+
+/// Redstone-style signal driven by the cached amount of a single key
+pub struct LevelEmitter<T: StoredItemType> {
+    pub mode: EmitterMode,
+    pub threshold: i32,
+    amount: i32,
+    signal: bool,
+    watcher: WatcherId,
+    changes: Receiver<StoredItemChange<T>>,
+}
+
+impl<T: StoredItemType> LevelEmitter<T> {
+    /// Watches `key` on `grid`, starting from its current amount
+    pub fn new<'a>(grid: &mut Grid<'a, T>, key: &'a T, threshold: i32, mode: EmitterMode) -> Self {
+        let amount = grid.stored_items_cache.get(key).map_or(0, |x| x.count);
+        let (watcher, changes) = grid.watch_channel(WatchFilter::key(key));
+        LevelEmitter {
+            mode,
+            threshold,
+            amount,
+            signal: mode.evaluate(amount, threshold),
+            watcher,
+            changes
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.signal
+    }
+
+    pub fn amount(&self) -> i32 {
+        self.amount
+    }
+
+    fn update(&mut self) -> Option<EmitterEdge> {
+        let signal = self.mode.evaluate(self.amount, self.threshold);
+        if signal == self.signal {
+            return None;
+        }
+        self.signal = signal;
+        Some(if signal { EmitterEdge::Rising } else { EmitterEdge::Falling })
+    }
+
+    /// Applies all changes since the last poll, returns every flip in order
+    pub fn poll(&mut self) -> Vec<EmitterEdge> {
+        let mut edges = vec![];
+        while let Ok(change) = self.changes.try_recv() {
+            self.amount = change.new;
+            edges.extend(self.update());
+        }
+        edges
+    }
+
+    /// Reconfiguring may flip the signal without any change in storage. Catches up with changes
+    /// not polled yet first, the edge is relative to the last signal seen.
+    pub fn configure(&mut self, threshold: i32, mode: EmitterMode) -> Option<EmitterEdge> {
+        while let Ok(change) = self.changes.try_recv() {
+            self.amount = change.new;
+        }
+        self.threshold = threshold;
+        self.mode = mode;
+        self.update()
+    }
+
+    pub fn detach(self, grid: &mut Grid<'_, T>) {
+        grid.unwatch(self.watcher);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::drive::{CellHost, CellHostKind};
+use crate::storage::{StorageCell, StorageCellKind, StoredItemType};
+use crate::topology::DeviceKind;
+use serde::Serialize;
+
+/// AE drawn for every item moved in or out of storage
+pub const ENERGY_PER_ITEM: f64 = 0.1;
+/// AE drawn for every craft a CPU dispatches
+pub const ENERGY_PER_CRAFT: f64 = 1.0;
+/// Buffer every network has without any energy cell
+pub const NETWORK_BUFFER: f64 = 800.0;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+pub enum EnergyCellKind {
+    Standard,
+    Dense
+}
+
+impl EnergyCellKind {
+    pub fn capacity(&self) -> f64 {
+        match self {
+            EnergyCellKind::Standard => 200_000.0,
+            EnergyCellKind::Dense => 1_600_000.0
+        }
+    }
+}
+
+impl<'a, T: StoredItemType> StorageCell<'a, T> {
+    /// AE/t drawn while the cell is in a network
+    pub fn idle_drain(&self) -> f64 {
+        if self.kind == StorageCellKind::Creative {
+            return 0.0;
+        }
+        match self.cell_type.get_capacity() {
+            x if x <= 1024 => 0.5,
+            x if x <= 4096 => 1.0,
+            x if x <= 16384 => 1.5,
+            _ => 2.0
+        }
+    }
+}
+
+impl<'a, T: StoredItemType> CellHost<'a, T> {
+    /// AE/t drawn by the host and all of its cells
+    pub fn idle_drain(&self) -> f64 {
+        let base = match self.kind {
+            CellHostKind::Drive => 0.5,
+            CellHostKind::Chest => 0.5
+        };
+        base + self.cells().map(|(_, cell)| cell.idle_drain()).sum::<f64>()
+    }
+}
+
+impl DeviceKind {
+    /// AE/t drawn by the device itself, hosted cells are accounted for by their grid
+    pub fn idle_drain(&self) -> f64 {
+        match self {
+            DeviceKind::Controller => 3.0,
+            DeviceKind::Cable(_) => 0.0,
+            DeviceKind::Drive | DeviceKind::Chest => 0.0,
+            DeviceKind::Terminal => 0.5,
+            DeviceKind::ImportBus | DeviceKind::ExportBus | DeviceKind::StorageBus => 1.0,
+            DeviceKind::Interface => 1.0
+        }
+    }
+}
+
+/// Energy buffer of a network, shared by all of its grids
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EnergyGrid {
+    pub stored: f64,
+    pub energy_cells: Vec<EnergyCellKind>,
+    /// AE/t injected by every acceptor
+    pub acceptors: Vec<f64>,
+    /// Cleared when a tick could not pay for the idle drain
+    pub online: bool,
+}
+
+impl Default for EnergyGrid {
+    fn default() -> Self {
+        EnergyGrid {
+            stored: 0.0,
+            energy_cells: vec![],
+            acceptors: vec![],
+            online: true
+        }
+    }
+}
+
+impl EnergyGrid {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn capacity(&self) -> f64 {
+        NETWORK_BUFFER + self.energy_cells.iter().map(|x| x.capacity()).sum::<f64>()
+    }
+
+    /// Stores as much as fits, returns the overflow
+    pub fn inject(&mut self, amount: f64) -> f64 {
+        let accepted = amount.min(self.capacity() - self.stored).max(0.0);
+        self.stored += accepted;
+        amount - accepted
+    }
+
+    /// Extracts `amount` only if all of it is available
+    pub fn extract(&mut self, amount: f64) -> bool {
+        if self.stored < amount {
+            return false;
+        }
+        self.stored -= amount;
+        true
+    }
+
+    pub fn can_afford(&self, amount: f64) -> bool {
+        self.online && self.stored >= amount
+    }
+
+    /// Runs acceptors and pays the idle drain, going offline when it cannot be paid.
+    /// Returns whether the network is online afterwards.
+    pub fn tick(&mut self, idle_drain: f64) -> bool {
+        let injection: f64 = self.acceptors.iter().sum();
+        self.inject(injection);
+        self.online = self.extract(idle_drain);
+        if !self.online {
+            // Whatever is left is not enough to keep devices running
+            self.stored = 0.0;
+        }
+        self.online
+    }
+
+    pub fn merge(&mut self, other: EnergyGrid) {
+        self.energy_cells.extend(other.energy_cells);
+        self.acceptors.extend(other.acceptors);
+        self.stored = (self.stored + other.stored).min(self.capacity());
+    }
+}
@@ -1,17 +1,42 @@
 use nbt::Blob;
-use std::ops::Add;
 use std::cmp::Ordering;
-use std::hash::Hash;
 use crate::storage::{StoredItemType, StoredItemTypes};
-use std::convert::TryInto;
 use serde::{Serialize, Deserialize, Serializer};
-use serde::ser::{Error};
+
+/// Physical properties, the defaults are those of water
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FluidProperties {
+    /// In kelvin
+    pub temperature: i32,
+    /// Water is 1000, higher flows slower
+    pub viscosity: i32,
+    /// Water is 1000, negative for fluids rising up
+    pub density: i32,
+    pub gaseous: bool,
+    /// Id of the item holding one bucket of the fluid
+    pub bucket: Option<String>,
+}
+
+impl Default for FluidProperties {
+    fn default() -> Self {
+        FluidProperties {
+            temperature: 300,
+            viscosity: 1000,
+            density: 1000,
+            gaseous: false,
+            bucket: None
+        }
+    }
+}
 
 /// Representing a "definition stack"
-#[derive(PartialEq, Debug, Deserialize)]
+#[derive(PartialEq, Debug, Clone, Deserialize)]
 pub struct Fluid {
     pub id: String,
     pub tag: nbt::Blob,
+    #[serde(default)]
+    pub properties: FluidProperties,
 }
 
 impl PartialOrd for Fluid {
@@ -44,7 +69,13 @@ impl Fluid {
     pub fn new(id: &str) -> Self {
         Fluid {
             id: id.to_string(),
-            tag: Blob::default()
+            tag: Blob::default(),
+            properties: FluidProperties::default()
         }
     }
+
+    pub fn with_properties(mut self, properties: FluidProperties) -> Self {
+        self.properties = properties;
+        self
+    }
 }
\ No newline at end of file
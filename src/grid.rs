@@ -1,8 +1,78 @@
-use crate::storage::{StorageCell, StoredItemType, StoredItem};
+use crate::storage::{StorageCell, StoredItemType, StoredItem, StorageCellKind, CellId, AccessMode};
+use crate::drive::{CellHost, HostId};
+use crate::energy::{EnergyGrid, ENERGY_PER_ITEM};
+use crate::watch::{Watchers, WatchFilter, WatchSink, WatcherId, StoredItemChange};
 use crate::item::Item;
+use crate::interface::Interface;
+use crate::registry::{Key, KeyId, KeyRegistry};
+use crate::storage::StoredItemTypes;
 use std::collections::{BTreeMap, BTreeSet};
+use std::cmp::{max, min};
 use serde::Serialize;
 use std::ops::Add;
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver};
+
+static NEXT_GRID_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Process-wide unique grid identifier, used to detect storage bus loops
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct GridId(usize);
+
+impl GridId {
+    pub(crate) fn next() -> Self {
+        GridId(NEXT_GRID_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// What a storage bus exposes as storage
+pub enum BusTarget<'a, T: StoredItemType> {
+    /// Another grid, seen through one of its interfaces
+    Grid(Rc<RefCell<Grid<'a, T>>>),
+    /// The slots of an interface itself
+    Interface(Rc<RefCell<Interface<'a, T>>>)
+}
+
+/// Storage bus placed on an interface, exposing another grid or the interface's own slots as storage
+pub struct StorageBus<'a, T: StoredItemType> {
+    pub id: GridId,
+    pub access: AccessMode,
+    pub target: BusTarget<'a, T>,
+}
+
+impl<'a, T: StoredItemType> fmt::Debug for StorageBus<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never descend into the target, the chain may loop back to us
+        f.debug_struct("StorageBus").field("id", &self.id).field("access", &self.access).finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum GridError {
+    /// The network is offline or cannot pay for the operation
+    PowerFailure
+}
+
+/// Location of a cell inside a grid
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum CellRef {
+    /// Index into `Grid::storage_cells`
+    Loose(usize),
+    /// Slot of a drive or chest
+    Hosted(HostId, usize),
+}
+
+/// Amounts to pull from each source for a single take, computed before anything is touched
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExtractionPlan {
+    pub cells: Vec<(CellRef, i32)>,
+    pub buses: Vec<(GridId, i32)>,
+    /// Never more than requested
+    pub total: i32,
+}
 
 pub struct InsertBatch {
 
@@ -11,150 +81,680 @@ pub struct InsertBatch {
 /// Network grid
 #[derive(Debug, Serialize)]
 pub struct Grid<'a, T: StoredItemType> {
+    pub id: GridId,
+
     pub storage_cells: Vec<StorageCell<'a, T>>,
 
+    /// Drives and chests
+    pub hosts: BTreeMap<HostId, CellHost<'a, T>>,
+
     pub stored_items_cache: BTreeMap<&'a T, StoredItem<'a, T>>,
 
-    pub stored_items_priority_cache: BTreeMap<&'a T, Vec<usize>>
+    pub stored_items_priority_cache: BTreeMap<&'a T, Vec<CellRef>>,
+
+    /// Online cells in insertion order, lowest priority first
+    #[serde(skip)]
+    pub cell_order: Vec<CellRef>,
+
+    /// Part of `stored_items_cache` seen through storage buses
+    #[serde(skip)]
+    external_items_cache: BTreeMap<&'a T, StoredItem<'a, T>>,
+
+    /// Sub-networks reached through storage buses, consulted after our own cells
+    #[serde(skip)]
+    pub storage_buses: Vec<StorageBus<'a, T>>,
+
+    /// Network energy, a grid without one never runs out of power
+    #[serde(skip)]
+    pub energy: Option<Rc<RefCell<EnergyGrid>>>,
+
+    /// Observers of `stored_items_cache`
+    #[serde(skip)]
+    pub watchers: Watchers<'a, T>,
 }
 
 impl<'a, T: StoredItemType> Default for Grid<'a, T> {
     fn default() -> Self {
         Grid {
+            id: GridId::next(),
             storage_cells: Vec::default(),
+            hosts: BTreeMap::default(),
             stored_items_cache: BTreeMap::default(),
-            stored_items_priority_cache: BTreeMap::default()
+            stored_items_priority_cache: BTreeMap::default(),
+            cell_order: Vec::default(),
+            external_items_cache: BTreeMap::default(),
+            storage_buses: Vec::default(),
+            energy: None,
+            watchers: Watchers::default(),
         }
     }
 }
 
 impl<'a, T: StoredItemType> Grid<'a, T> {
     pub fn sort(&mut self) {
+        self.storage_cells.sort();
         self.refresh_cache();
-        let stored_items: Vec<StoredItem<'a, T>> = self.stored_items_cache.iter().map(|x| x.1.clone()).collect();
-        self.stored_items_cache.clear();
+        // Creative cells would swallow everything and read-only cells could not be refilled,
+        // keep both out of the redistribution
+        let standard_cells: Vec<CellRef> = self.cell_order.iter()
+            .copied()
+            .filter(|x| self.cell(*x).is_some_and(|cell| cell.kind == StorageCellKind::Standard && cell.config.access.can_insert()))
+            .collect();
+        // Only redistribute what lives in our own cells, sub-networks keep their items
+        let mut local_items = BTreeMap::new();
+        let mut previous = vec![];
+        for cell_ref in standard_cells.iter() {
+            if let Some(cell) = self.cell_mut(*cell_ref) {
+                for stored_item in cell.stored_items.values() {
+                    Self::merge_into(&mut local_items, stored_item);
+                }
+                previous.push((*cell_ref, cell.clone()));
+                cell.clear();
+            }
+        }
+        self.cell_order = standard_cells;
         self.stored_items_priority_cache.clear();
-        for x in self.storage_cells.iter_mut() {
-            x.clear();
+        let remaining = self.do_insert_many(local_items.into_values().collect());
+        // Items never leave the grid while sorting, if something no longer fits the cells stay as they were
+        if remaining.iter().any(|x| *x > 0) {
+            for (cell_ref, cell) in previous {
+                if let Some(slot) = self.cell_mut(cell_ref) {
+                    *slot = cell;
+                }
+            }
         }
-        self.storage_cells.sort();
-        self.insert_many(stored_items);
         self.refresh_cache();
     }
 
+    pub fn cell(&self, cell_ref: CellRef) -> Option<&StorageCell<'a, T>> {
+        match cell_ref {
+            CellRef::Loose(index) => self.storage_cells.get(index),
+            CellRef::Hosted(host, slot) => self.hosts.get(&host)
+                .and_then(|x| x.slots.get(slot))
+                .and_then(|x| x.as_ref())
+        }
+    }
+
+    pub fn cell_mut(&mut self, cell_ref: CellRef) -> Option<&mut StorageCell<'a, T>> {
+        match cell_ref {
+            CellRef::Loose(index) => self.storage_cells.get_mut(index),
+            CellRef::Hosted(host, slot) => self.hosts.get_mut(&host)
+                .and_then(|x| x.slots.get_mut(slot))
+                .and_then(|x| x.as_mut())
+        }
+    }
+
+    /// Priority of a cell, hosted cells share the priority of their host
+    pub fn cell_priority(&self, cell_ref: CellRef) -> i32 {
+        match cell_ref {
+            CellRef::Loose(index) => self.storage_cells.get(index).map_or(0, |x| x.config.priority),
+            CellRef::Hosted(host, _) => self.hosts.get(&host).map_or(0, |x| x.priority)
+        }
+    }
+
+    fn order_key(&self, cell_ref: CellRef) -> (i32, CellRef) {
+        (self.cell_priority(cell_ref), cell_ref)
+    }
+
+    fn merge_into(cache: &mut BTreeMap<&'a T, StoredItem<'a, T>>, stored_item: &StoredItem<'a, T>) {
+        if let Some(cached_item) = cache.get_mut(stored_item.item) {
+            cached_item.count = cached_item.count.saturating_add(stored_item.count);
+        } else {
+            cache.insert(stored_item.item, stored_item.clone());
+        }
+    }
+
     pub fn refresh_cache(&mut self) {
-        self.stored_items_cache.clear();
-        self.stored_items_priority_cache.clear();
-        for (cell_index, cell) in self.storage_cells.iter().enumerate() {
-            for (item, stored_item) in cell.stored_items.iter() {
-                if self.stored_items_cache.contains_key(item) {
-                    let mut cached_item = self.stored_items_cache.get_mut(item).unwrap();
-                    cached_item.count += stored_item.count;
-                    let mut priority_list = self.stored_items_priority_cache.get_mut(item).unwrap();
-                    priority_list.push(cell_index);
-                } else {
-                    self.stored_items_cache.insert(item, stored_item.clone());
-                    self.stored_items_priority_cache.insert(item, vec![cell_index]);
+        let mut cell_order: Vec<CellRef> = (0..self.storage_cells.len()).map(CellRef::Loose).collect();
+        for host in self.hosts.values().filter(|x| x.is_online()) {
+            cell_order.extend(host.cells().map(|(slot, _)| CellRef::Hosted(host.id, slot)));
+        }
+        cell_order.sort_by_key(|x| self.order_key(*x));
+        self.cell_order = cell_order;
+
+        let mut stored_items_cache = BTreeMap::new();
+        let mut stored_items_priority_cache: BTreeMap<&'a T, Vec<CellRef>> = BTreeMap::new();
+        for cell_ref in self.cell_order.iter() {
+            if let Some(cell) = self.cell(*cell_ref) {
+                for stored_item in cell.stored_items.values() {
+                    Self::merge_into(&mut stored_items_cache, stored_item);
+                    stored_items_priority_cache.entry(stored_item.item).or_default().push(*cell_ref);
                 }
             }
         }
+
+        let mut visited = BTreeSet::new();
+        visited.insert(self.id);
+        let mut external = BTreeMap::new();
+        self.collect_external(&mut visited, &mut external);
+        for stored_item in external.values() {
+            Self::merge_into(&mut stored_items_cache, stored_item);
+        }
+        let old_cache = std::mem::replace(&mut self.stored_items_cache, stored_items_cache);
+        self.stored_items_priority_cache = stored_items_priority_cache;
+        self.external_items_cache = external;
+
+        if !self.watchers.is_empty() {
+            let keys: BTreeSet<&'a T> = old_cache.keys().chain(self.stored_items_cache.keys()).copied().collect();
+            for item in keys {
+                let old = old_cache.get(item).map_or(0, |x| x.count);
+                self.notify(item, old);
+            }
+        }
     }
 
-    pub fn insert_storage_cell(&mut self, cell: StorageCell<'a, T>) {
-        self.storage_cells.push(cell);
-        self.storage_cells.sort();
-        self.refresh_cache();
+    /// Tells watchers about `item` if its cached amount differs from `old`
+    fn notify(&mut self, item: &'a T, old: i32) {
+        let new = self.stored_items_cache.get(item).map_or(0, |x| x.count);
+        self.watchers.notify(item, old, new);
     }
 
-    fn do_insert_many(&mut self, items: Vec<StoredItem<'a, T>>) -> Vec<i32> {
-        let mut item_keys = BTreeSet::new();
-        let mut remaining_count: Vec<i32> = items.iter().map(|x| x.count).collect();
-        for x in items.iter() {
-            item_keys.insert(x.item);
-            remaining_count.push(x.count);
-        }
-        let item_keys = item_keys;
-
-        let priority_caches: BTreeMap<&'a T, &Vec<usize>> =
-            self.stored_items_priority_cache
-                .iter()
-                .filter(|(item, _)| item_keys.contains(*item))
-                .map(|(item, value)| (*item, value))
-                .collect();
-
-        for (i, item) in items.iter().enumerate() {
-            let mut count = remaining_count.get_mut(i).unwrap();
-            if priority_caches.contains_key(item.item) {
-                let priority_list = *priority_caches.get(item.item).unwrap();
-                for cell_index in priority_list.iter() {
-                    if *count > 0 {
-                        if let Some(storage_cell) = self.storage_cells.get_mut(*cell_index) {
-                            if !storage_cell.is_full() {
-                                let to_insert = StoredItem {
-                                    item: item.item,
-                                    count: count.clone()
-                                };
-                                *count -= storage_cell.insert(to_insert);
-                            }
-                        }
-                    }
+    pub fn watch(&mut self, filter: WatchFilter<'a, T>, sink: WatchSink<T>) -> WatcherId {
+        self.watchers.add(filter, sink)
+    }
+
+    /// Subscribes through a channel, the watcher goes away when the receiver is dropped
+    pub fn watch_channel(&mut self, filter: WatchFilter<'a, T>) -> (WatcherId, Receiver<StoredItemChange<T>>) {
+        let (sender, receiver) = mpsc::channel();
+        (self.watch(filter, WatchSink::Channel(sender)), receiver)
+    }
+
+    pub fn unwatch(&mut self, id: WatcherId) -> bool {
+        self.watchers.remove(id)
+    }
+
+    /// Adds a newly available cell to the caches without rescanning the others
+    fn cache_add_cell(&mut self, cell_ref: CellRef) {
+        let key = self.order_key(cell_ref);
+        let position = self.cell_order.partition_point(|x| self.order_key(*x) < key);
+        self.cell_order.insert(position, cell_ref);
+        let stored_items: Vec<StoredItem<'a, T>> = match self.cell(cell_ref) {
+            Some(cell) => cell.stored_items.values().cloned().collect(),
+            None => return
+        };
+        for stored_item in stored_items {
+            let position = self.stored_items_priority_cache.get(stored_item.item)
+                .map_or(0, |list| list.partition_point(|x| self.order_key(*x) < key));
+            self.stored_items_priority_cache.entry(stored_item.item).or_default().insert(position, cell_ref);
+            let old = self.stored_items_cache.get(stored_item.item).map_or(0, |x| x.count);
+            Self::merge_into(&mut self.stored_items_cache, &stored_item);
+            self.notify(stored_item.item, old);
+        }
+    }
+
+    /// Drops a cell from the caches, must be called while the cell is still in place
+    fn cache_remove_cell(&mut self, cell_ref: CellRef) {
+        self.cell_order.retain(|x| *x != cell_ref);
+        let items: Vec<&'a T> = match self.cell(cell_ref) {
+            Some(cell) => cell.stored_items.keys().copied().collect(),
+            None => return
+        };
+        for item in items {
+            if let Some(priority_list) = self.stored_items_priority_cache.get_mut(item) {
+                priority_list.retain(|x| *x != cell_ref);
+            }
+            self.recount(item);
+        }
+    }
+
+    /// Recomputes the cached total of a single item from the cells holding it
+    fn recount(&mut self, item: &'a T) {
+        let old = self.stored_items_cache.get(item).map_or(0, |x| x.count);
+        let mut count = self.external_items_cache.get(item).map(|x| x.count);
+        if let Some(priority_list) = self.stored_items_priority_cache.get(item) {
+            for cell_ref in priority_list.iter() {
+                if let Some(stored_item) = self.cell(*cell_ref).and_then(|x| x.stored_items.get(item)) {
+                    count = Some(count.unwrap_or(0).saturating_add(stored_item.count));
+                }
+            }
+            if priority_list.is_empty() {
+                self.stored_items_priority_cache.remove(item);
+            }
+        }
+        match count {
+            Some(count) => {
+                self.stored_items_cache.insert(item, StoredItem::new(item, count));
+            }
+            None => {
+                self.stored_items_cache.remove(item);
+            }
+        }
+        self.notify(item, old);
+    }
+
+    /// Sums up the contents of our own online cells into `cache`
+    fn collect_cells(&self, cache: &mut BTreeMap<&'a T, StoredItem<'a, T>>) {
+        for cell_ref in self.cell_order.iter() {
+            if let Some(cell) = self.cell(*cell_ref) {
+                for stored_item in cell.stored_items.values() {
+                    Self::merge_into(cache, stored_item);
                 }
             }
-            // Still remain items to be inserted
-            if *count > 0 {
-                for cell in self.storage_cells.iter_mut() {
-                    if *count == 0 {
-                        break;
+        }
+    }
+
+    /// Sums up everything reachable through storage buses, skipping grids already in `visited`
+    fn collect_external(&self, visited: &mut BTreeSet<GridId>, cache: &mut BTreeMap<&'a T, StoredItem<'a, T>>) {
+        for bus in self.storage_buses.iter() {
+            if !visited.insert(bus.id) {
+                continue;
+            }
+            // A failed borrow means the target is being modified up the call chain, i.e. a loop
+            match &bus.target {
+                BusTarget::Grid(grid) => {
+                    if let Ok(grid) = grid.try_borrow() {
+                        grid.collect_cells(cache);
+                        grid.collect_external(visited, cache);
                     }
-                    if !cell.is_full() {
-                        let to_insert = StoredItem {
-                            item: item.item,
-                            count: count.clone()
-                        };
-                        *count -= cell.insert(to_insert);
+                }
+                BusTarget::Interface(interface) => {
+                    if let Ok(interface) = interface.try_borrow() {
+                        for stack in interface.inventory.stacks() {
+                            Self::merge_into(cache, stack);
+                        }
                     }
                 }
             }
         }
+    }
 
-        remaining_count
+    /// Attaches a storage bus to another grid. Loops are allowed, they are cut at the first grid seen twice.
+    pub fn attach_grid(&mut self, grid: Rc<RefCell<Grid<'a, T>>>) {
+        // Attaching a grid to itself, it is mutably borrowed already
+        let id = if std::ptr::eq(grid.as_ptr(), self) { self.id } else { grid.borrow().id };
+        self.storage_buses.push(StorageBus {
+            id,
+            access: AccessMode::ReadWrite,
+            target: BusTarget::Grid(grid)
+        });
+        self.refresh_cache();
     }
 
-    pub fn insert_many(&mut self, items: Vec<StoredItem<'a, T>>) -> Vec<i32> {
-        let ret = self.do_insert_many(items);
+    /// Attaches a storage bus to the slots of an interface, which may well be connected to this grid
+    pub fn attach_interface(&mut self, interface: Rc<RefCell<Interface<'a, T>>>) {
+        let id = interface.borrow().id;
+        self.storage_buses.push(StorageBus {
+            id,
+            access: AccessMode::ReadWrite,
+            target: BusTarget::Interface(interface)
+        });
         self.refresh_cache();
-        ret
     }
 
-    fn do_insert(&mut self, item: StoredItem<'a, T>) -> i32 {
+    pub fn set_bus_access(&mut self, id: GridId, access: AccessMode) {
+        for bus in self.storage_buses.iter_mut().filter(|x| x.id == id) {
+            bus.access = access;
+        }
+    }
+
+    pub fn detach_grid(&mut self, id: GridId) -> Option<Rc<RefCell<Grid<'a, T>>>> {
+        match self.detach(id, |x| matches!(x, BusTarget::Grid(_)))? {
+            BusTarget::Grid(grid) => Some(grid),
+            BusTarget::Interface(_) => None
+        }
+    }
+
+    pub fn detach_interface(&mut self, id: GridId) -> Option<Rc<RefCell<Interface<'a, T>>>> {
+        match self.detach(id, |x| matches!(x, BusTarget::Interface(_)))? {
+            BusTarget::Interface(interface) => Some(interface),
+            BusTarget::Grid(_) => None
+        }
+    }
+
+    fn detach<F: Fn(&BusTarget<'a, T>) -> bool>(&mut self, id: GridId, kind: F) -> Option<BusTarget<'a, T>> {
+        let index = self.storage_buses.iter().position(|x| x.id == id && kind(&x.target))?;
+        let bus = self.storage_buses.remove(index);
+        self.refresh_cache();
+        Some(bus.target)
+    }
+
+    fn insert_external(&mut self, item: StoredItem<'a, T>, visited: &mut BTreeSet<GridId>) -> i32 {
         let mut count = item.count;
+        for bus in self.storage_buses.iter() {
+            if count == 0 {
+                break;
+            }
+            if !bus.access.can_insert() || !visited.insert(bus.id) {
+                continue;
+            }
+            count = match &bus.target {
+                BusTarget::Grid(grid) => match grid.try_borrow_mut() {
+                    Ok(mut grid) => grid.insert_visited(StoredItem::new(item.item, count), visited),
+                    Err(_) => count
+                },
+                BusTarget::Interface(interface) => match interface.try_borrow_mut() {
+                    Ok(mut interface) => interface.insert(StoredItem::new(item.item, count)),
+                    Err(_) => count
+                }
+            };
+        }
+        count
+    }
+
+    /// Decides how much to take from every cell and storage bus, highest priority first.
+    /// Buses are planned from their caches, they may deliver less but never more.
+    pub fn plan_extraction(&self, item: &StoredItem<'a, T>) -> ExtractionPlan {
+        self.plan_visited(item, &BTreeSet::new())
+    }
+
+    /// Plan leaving out the storage buses in `visited`
+    fn plan_visited(&self, item: &StoredItem<'a, T>, visited: &BTreeSet<GridId>) -> ExtractionPlan {
+        let mut remaining = max(item.count, 0);
+        let mut cells = vec![];
         if let Some(priority_list) = self.stored_items_priority_cache.get(item.item) {
-            for cell_index in priority_list.iter() {
+            for cell_ref in priority_list.iter().rev() { // Reverse order take out
+                if remaining == 0 {
+                    break;
+                }
+                let available = self.cell(*cell_ref)
+                    .filter(|x| x.config.access.can_extract())
+                    .and_then(|x| x.stored_items.get(item.item))
+                    .map_or(0, |x| x.count);
+                let count = min(available, remaining);
                 if count > 0 {
-                    if let Some(storage_cell) = self.storage_cells.get_mut(*cell_index) {
-                        if !storage_cell.is_full() {
-                            let to_insert = StoredItem {
-                                item: item.item,
-                                count
-                            };
-                            count -= storage_cell.insert(to_insert);
-                        }
+                    cells.push((*cell_ref, count));
+                    remaining -= count;
+                }
+            }
+        }
+        let mut buses = vec![];
+        for bus in self.storage_buses.iter().filter(|x| x.access.can_extract() && !visited.contains(&x.id)) {
+            if remaining == 0 {
+                break;
+            }
+            let available = match &bus.target {
+                BusTarget::Grid(grid) => grid.try_borrow()
+                    .ok()
+                    .and_then(|x| x.stored_items_cache.get(item.item).map(|x| x.count))
+                    .unwrap_or(0),
+                BusTarget::Interface(interface) => interface.try_borrow().map_or(0, |x| x.inventory.count(item.item))
+            };
+            let count = min(available, remaining);
+            if count > 0 {
+                buses.push((bus.id, count));
+                remaining -= count;
+            }
+        }
+        ExtractionPlan {
+            cells,
+            buses,
+            total: max(item.count, 0) - remaining
+        }
+    }
+
+    fn take_planned(&mut self, item: &'a T, plan: &ExtractionPlan, visited: &mut BTreeSet<GridId>) -> i32 {
+        let mut taken_count = 0;
+        for (cell_ref, count) in plan.cells.iter() {
+            if let Some(storage_cell) = self.cell_mut(*cell_ref) {
+                taken_count += storage_cell.take(&StoredItem::new(item, *count));
+            }
+        }
+        for (id, count) in plan.buses.iter() {
+            if !visited.insert(*id) {
+                continue;
+            }
+            match self.storage_buses.iter().find(|x| x.id == *id).map(|x| &x.target) {
+                Some(BusTarget::Grid(grid)) => {
+                    if let Ok(mut grid) = grid.try_borrow_mut() {
+                        taken_count += grid.take_visited(StoredItem::new(item, *count), visited);
+                    }
+                }
+                Some(BusTarget::Interface(interface)) => {
+                    if let Ok(mut interface) = interface.try_borrow_mut() {
+                        taken_count += interface.extract(item, *count);
                     }
                 }
+                None => {}
             }
         }
-        // Still remain items to be inserted
+        taken_count
+    }
+
+    /// Local cells first, then storage buses
+    fn insert_routed(&mut self, item: StoredItem<'a, T>, visited: &mut BTreeSet<GridId>) -> i32 {
+        let key = item.item;
+        let count = self.do_insert(item);
         if count > 0 {
-            for cell in self.storage_cells.iter_mut() {
-                if count == 0 {
-                    return 0;
+            return self.insert_external(StoredItem::new(key, count), visited);
+        }
+        count
+    }
+
+    fn insert_visited(&mut self, item: StoredItem<'a, T>, visited: &mut BTreeSet<GridId>) -> i32 {
+        if self.check_power(item.count).is_err() {
+            return item.count;
+        }
+        let requested = item.count;
+        let count = self.insert_routed(item, visited);
+        self.refresh_cache();
+        self.consume_power(requested - count);
+        count
+    }
+
+    fn take_visited(&mut self, item: StoredItem<'a, T>, visited: &mut BTreeSet<GridId>) -> i32 {
+        let plan = self.plan_visited(&item, visited);
+        if self.check_power(plan.total).is_err() {
+            return 0;
+        }
+        let taken_count = self.take_planned(item.item, &plan, visited);
+        self.refresh_cache();
+        self.consume_power(taken_count);
+        taken_count
+    }
+
+    /// Fails if the network is offline or cannot pay for moving `count` items
+    pub fn check_power(&self, count: i32) -> Result<(), GridError> {
+        match &self.energy {
+            Some(energy) if !energy.borrow().can_afford(count as f64 * ENERGY_PER_ITEM) => Err(GridError::PowerFailure),
+            _ => Ok(())
+        }
+    }
+
+    fn consume_power(&self, count: i32) {
+        self.consume_energy(count as f64 * ENERGY_PER_ITEM).ok();
+    }
+
+    /// Draws energy for work done on behalf of the grid, e.g. crafting
+    pub fn consume_energy(&self, amount: f64) -> Result<(), GridError> {
+        match &self.energy {
+            Some(energy) => {
+                let mut energy = energy.borrow_mut();
+                if energy.online && energy.extract(amount) {
+                    Ok(())
+                } else {
+                    Err(GridError::PowerFailure)
+                }
+            }
+            None => Ok(())
+        }
+    }
+
+    /// AE/t drawn by all cells, drives and chests
+    pub fn idle_drain(&self) -> f64 {
+        self.storage_cells.iter().map(|x| x.idle_drain()).sum::<f64>()
+            + self.hosts.values().map(|x| x.idle_drain()).sum::<f64>()
+    }
+
+    /// Like `insert`, but reports a power failure instead of silently inserting nothing
+    pub fn try_insert(&mut self, item: StoredItem<'a, T>) -> Result<i32, GridError> {
+        self.check_power(item.count)?;
+        Ok(self.insert(item))
+    }
+
+    /// Like `take`, but reports a power failure instead of silently taking nothing
+    pub fn try_take(&mut self, item: StoredItem<'a, T>) -> Result<i32, GridError> {
+        self.check_power(self.plan_extraction(&item).total)?;
+        Ok(self.take(item))
+    }
+
+    pub fn try_insert_many(&mut self, items: Vec<StoredItem<'a, T>>) -> Result<Vec<i32>, GridError> {
+        self.check_power(items.iter().fold(0, |acc, x| acc.saturating_add(x.count)))?;
+        Ok(self.insert_many(items))
+    }
+
+    pub fn insert_storage_cell(&mut self, cell: StorageCell<'a, T>) -> CellId {
+        let id = cell.id;
+        self.storage_cells.push(cell);
+        self.storage_cells.sort();
+        self.refresh_cache();
+        id
+    }
+
+    /// Current location of a cell, loose cell indices change with `sort`
+    pub fn locate_cell(&self, id: CellId) -> Option<CellRef> {
+        if let Some(index) = self.storage_cells.iter().position(|x| x.id == id) {
+            return Some(CellRef::Loose(index));
+        }
+        self.hosts.values()
+            .flat_map(|host| host.cells().map(move |(slot, cell)| (host.id, slot, cell)))
+            .find(|(_, _, cell)| cell.id == id)
+            .map(|(host, slot, _)| CellRef::Hosted(host, slot))
+    }
+
+    pub fn cell_by_id(&self, id: CellId) -> Option<&StorageCell<'a, T>> {
+        self.cell(self.locate_cell(id)?)
+    }
+
+    pub fn cell_by_id_mut(&mut self, id: CellId) -> Option<&mut StorageCell<'a, T>> {
+        let cell_ref = self.locate_cell(id)?;
+        self.cell_mut(cell_ref)
+    }
+
+    /// Takes a loose or hosted cell out of the grid, updating the caches for that cell only
+    pub fn remove_storage_cell(&mut self, id: CellId) -> Option<StorageCell<'a, T>> {
+        match self.locate_cell(id)? {
+            CellRef::Loose(index) => {
+                self.cache_remove_cell(CellRef::Loose(index));
+                let cell = self.storage_cells.remove(index);
+                // Following cells moved down by one, their relative order is unchanged
+                let shift = |x: &mut CellRef| {
+                    if let CellRef::Loose(i) = x {
+                        if *i > index {
+                            *i -= 1;
+                        }
+                    }
+                };
+                self.cell_order.iter_mut().for_each(shift);
+                for priority_list in self.stored_items_priority_cache.values_mut() {
+                    priority_list.iter_mut().for_each(shift);
                 }
-                if !cell.is_full() {
+                Some(cell)
+            }
+            CellRef::Hosted(host, slot) => self.eject_cell(host, slot)
+        }
+    }
+
+    pub fn add_host(&mut self, host: CellHost<'a, T>) -> HostId {
+        let id = host.id;
+        self.hosts.insert(id, host);
+        self.update_host(id, |_| {});
+        id
+    }
+
+    pub fn remove_host(&mut self, id: HostId) -> Option<CellHost<'a, T>> {
+        for cell_ref in self.hosted_cells(id) {
+            self.cache_remove_cell(cell_ref);
+        }
+        self.hosts.remove(&id)
+    }
+
+    /// Cached cells of a host, empty while the host is offline
+    fn hosted_cells(&self, id: HostId) -> Vec<CellRef> {
+        self.cell_order.iter()
+            .copied()
+            .filter(|x| matches!(x, CellRef::Hosted(host, _) if *host == id))
+            .collect()
+    }
+
+    /// Applies `f` to a host, refreshing the caches of its cells only
+    pub fn update_host<F: FnOnce(&mut CellHost<'a, T>)>(&mut self, id: HostId, f: F) {
+        for cell_ref in self.hosted_cells(id) {
+            self.cache_remove_cell(cell_ref);
+        }
+        let slots: Vec<usize> = match self.hosts.get_mut(&id) {
+            Some(host) => {
+                f(host);
+                if !host.is_online() {
+                    return;
+                }
+                host.cells().map(|x| x.0).collect()
+            }
+            None => return
+        };
+        for slot in slots {
+            self.cache_add_cell(CellRef::Hosted(id, slot));
+        }
+    }
+
+    pub fn set_host_powered(&mut self, id: HostId, powered: bool) {
+        self.update_host(id, |x| x.powered = powered);
+    }
+
+    pub fn set_host_priority(&mut self, id: HostId, priority: i32) {
+        self.update_host(id, |x| x.priority = priority);
+    }
+
+    /// Hot-swaps a cell into a host slot, giving it back if the slot is unavailable
+    pub fn mount_cell(&mut self, id: HostId, slot: usize, cell: StorageCell<'a, T>) -> Result<(), StorageCell<'a, T>> {
+        let host = match self.hosts.get_mut(&id) {
+            Some(host) => host,
+            None => return Err(cell)
+        };
+        host.mount(slot, cell)?;
+        if host.is_online() {
+            self.cache_add_cell(CellRef::Hosted(id, slot));
+        }
+        Ok(())
+    }
+
+    /// Hot-swaps a cell out of a host slot
+    pub fn eject_cell(&mut self, id: HostId, slot: usize) -> Option<StorageCell<'a, T>> {
+        let cell_ref = CellRef::Hosted(id, slot);
+        if self.cell_order.contains(&cell_ref) {
+            self.cache_remove_cell(cell_ref);
+        }
+        self.hosts.get_mut(&id)?.eject(slot)
+    }
+
+    fn do_insert_many(&mut self, items: Vec<StoredItem<'a, T>>) -> Vec<i32> {
+        items.into_iter().map(|x| self.do_insert(x)).collect()
+    }
+
+    pub fn insert_many(&mut self, items: Vec<StoredItem<'a, T>>) -> Vec<i32> {
+        let requested: Vec<i32> = items.iter().map(|x| x.count).collect();
+        if self.check_power(requested.iter().fold(0, |acc, x| acc.saturating_add(*x))).is_err() {
+            return requested;
+        }
+        let keys: Vec<&'a T> = items.iter().map(|x| x.item).collect();
+        let mut ret = self.do_insert_many(items);
+        for (i, key) in keys.into_iter().enumerate() {
+            if ret[i] > 0 {
+                let mut visited = BTreeSet::new();
+                visited.insert(self.id);
+                ret[i] = self.insert_external(StoredItem::new(key, ret[i]), &mut visited);
+            }
+        }
+        self.refresh_cache();
+        self.consume_power(requested.iter().zip(ret.iter()).fold(0, |acc, (x, y)| acc.saturating_add(x - y)));
+        ret
+    }
+
+    fn do_insert(&mut self, item: StoredItem<'a, T>) -> i32 {
+        let mut count = item.count;
+        // Cells already holding the item first, then everything else
+        let mut candidates = self.stored_items_priority_cache.get(item.item).cloned().unwrap_or_default();
+        candidates.extend(self.cell_order.iter().copied());
+        for cell_ref in candidates {
+            if count == 0 {
+                break;
+            }
+            if let Some(storage_cell) = self.cell_mut(cell_ref) {
+                if storage_cell.config.access.can_insert() && !storage_cell.is_full() {
                     let to_insert = StoredItem {
                         item: item.item,
                         count
                     };
-                    count -= cell.insert(to_insert);
+                    count -= storage_cell.insert(to_insert);
                 }
             }
         }
@@ -162,41 +762,163 @@ impl<'a, T: StoredItemType> Grid<'a, T> {
     }
 
     pub fn insert(&mut self, item: StoredItem<'a, T>) -> i32 {
-        let ret = self.do_insert(item);
-        self.refresh_cache();
-        ret
+        let mut visited = BTreeSet::new();
+        visited.insert(self.id);
+        self.insert_visited(item, &mut visited)
     }
 
-    fn do_take(&mut self, item: StoredItem<'a, T>) -> i32 {
+    pub fn take(&mut self, item: StoredItem<'a, T>) -> i32 {
+        let mut visited = BTreeSet::new();
+        visited.insert(self.id);
+        self.take_visited(item, &mut visited)
+    }
+
+    /// Dry run of `insert`, returns what would be left over
+    pub fn simulate_insert(&self, item: &StoredItem<'a, T>) -> i32 {
+        let mut visited = BTreeSet::new();
+        visited.insert(self.id);
+        self.simulate_insert_visited(item, &mut visited)
+    }
+
+    fn simulate_insert_visited(&self, item: &StoredItem<'a, T>, visited: &mut BTreeSet<GridId>) -> i32 {
+        if self.check_power(item.count).is_err() {
+            return item.count;
+        }
         let mut count = item.count;
-        let mut taken_count = 0;
-        if let Some(priority_list) = self.stored_items_priority_cache.get(item.item) {
-            for cell_index in priority_list.iter().rev() { // Reverse order take out
-                if count > 0 {
-                    if let Some(storage_cell) = self.storage_cells.get_mut(*cell_index) {
-                        let result = storage_cell.take(&item);
-                        taken_count += result;
-                        count -= result;
-                    }
+        // Same candidate order as `do_insert`, every cell counted once
+        let mut candidates = self.stored_items_priority_cache.get(item.item).cloned().unwrap_or_default();
+        candidates.extend(self.cell_order.iter().copied());
+        let mut seen = BTreeSet::new();
+        for cell_ref in candidates {
+            if count == 0 {
+                break;
+            }
+            if !seen.insert(cell_ref) {
+                continue;
+            }
+            if let Some(storage_cell) = self.cell(cell_ref) {
+                if storage_cell.config.access.can_insert() && !storage_cell.is_full() {
+                    count -= min(count, storage_cell.get_free_space(&StoredItem::new(item.item, count)));
                 }
             }
         }
-        taken_count
+        for bus in self.storage_buses.iter() {
+            if count == 0 {
+                break;
+            }
+            if !bus.access.can_insert() || !visited.insert(bus.id) {
+                continue;
+            }
+            count = match &bus.target {
+                BusTarget::Grid(grid) => match grid.try_borrow() {
+                    Ok(grid) => grid.simulate_insert_visited(&StoredItem::new(item.item, count), visited),
+                    Err(_) => count
+                },
+                BusTarget::Interface(interface) => match interface.try_borrow() {
+                    Ok(interface) => interface.simulate_insert(&StoredItem::new(item.item, count)),
+                    Err(_) => count
+                }
+            };
+        }
+        count
     }
 
-    pub fn take(&mut self, item: StoredItem<'a, T>) -> i32 {
-        let ret = self.do_take(item);
-        self.refresh_cache();
-        ret
+    /// Dry run of `take`, returns how many would be taken
+    pub fn simulate_take(&self, item: &StoredItem<'a, T>) -> i32 {
+        self.simulate_take_visited(item, &BTreeSet::new())
+    }
+
+    fn simulate_take_visited(&self, item: &StoredItem<'a, T>, visited: &BTreeSet<GridId>) -> i32 {
+        let plan = self.plan_visited(item, visited);
+        if self.check_power(plan.total).is_err() {
+            return 0;
+        }
+        plan.total
+    }
+
+    /// Our own id plus a storage bus to stay away from
+    fn bypass(&self, id: GridId) -> BTreeSet<GridId> {
+        vec![self.id, id].into_iter().collect()
+    }
+
+    /// `insert` that never routes into the storage bus `id`, for devices that are storage of this grid themselves
+    pub fn insert_bypassing(&mut self, item: StoredItem<'a, T>, id: GridId) -> i32 {
+        self.insert_visited(item, &mut self.bypass(id))
+    }
+
+    /// `take` that never pulls from the storage bus `id`
+    pub fn take_bypassing(&mut self, item: StoredItem<'a, T>, id: GridId) -> i32 {
+        self.take_visited(item, &mut self.bypass(id))
+    }
+
+    pub fn simulate_insert_bypassing(&self, item: &StoredItem<'a, T>, id: GridId) -> i32 {
+        self.simulate_insert_visited(item, &mut self.bypass(id))
+    }
+
+    pub fn simulate_take_bypassing(&self, item: &StoredItem<'a, T>, id: GridId) -> i32 {
+        self.simulate_take_visited(item, &self.bypass(id))
     }
 
     pub fn union(&mut self, other: Self) {
         for x in other.storage_cells.into_iter() {
             self.storage_cells.push(x);
         }
+        self.hosts.extend(other.hosts);
+        if self.energy.is_none() {
+            self.energy = other.energy;
+        }
+        for x in other.storage_buses.into_iter() {
+            if x.id != self.id {
+                self.storage_buses.push(x);
+            }
+        }
         self.storage_cells.sort();
         self.refresh_cache();
     }
+
+    /// Inverse of `union`: moves every cell matching `predicate` into a new grid.
+    /// Hosted cells cannot leave their drive, selecting one moves the whole host.
+    /// Storage buses stay here. Split repeatedly to get more than two grids.
+    /// A powered grid hands the new one an empty energy buffer of its own.
+    pub fn split_off<F: FnMut(&StorageCell<'a, T>) -> bool>(&mut self, mut predicate: F) -> Self {
+        let hosts: BTreeSet<HostId> = self.hosts.values()
+            .filter(|host| host.cells().any(|(_, cell)| predicate(cell)))
+            .map(|host| host.id)
+            .collect();
+        self.split_off_with(predicate, |x| hosts.contains(&x.id))
+    }
+
+    pub fn split_off_cells(&mut self, ids: &BTreeSet<CellId>) -> Self {
+        self.split_off(|x| ids.contains(&x.id))
+    }
+
+    /// Moves whole drives and chests into a new grid, loose cells stay
+    pub fn split_off_hosts(&mut self, ids: &BTreeSet<HostId>) -> Self {
+        self.split_off_with(|_| false, |x| ids.contains(&x.id))
+    }
+
+    fn split_off_with<F, H>(&mut self, mut cell_predicate: F, mut host_predicate: H) -> Self
+        where F: FnMut(&StorageCell<'a, T>) -> bool, H: FnMut(&CellHost<'a, T>) -> bool {
+        let mut grid = Grid {
+            energy: self.energy.as_ref().map(|_| Rc::new(RefCell::new(EnergyGrid::new()))),
+            ..Grid::default()
+        };
+        let (moved, kept): (Vec<_>, Vec<_>) = self.storage_cells.drain(..).partition(|x| cell_predicate(x));
+        self.storage_cells = kept;
+        grid.storage_cells = moved;
+        let host_ids: Vec<HostId> = self.hosts.values()
+            .filter(|x| host_predicate(x))
+            .map(|x| x.id)
+            .collect();
+        for id in host_ids {
+            if let Some(host) = self.hosts.remove(&id) {
+                grid.hosts.insert(id, host);
+            }
+        }
+        self.refresh_cache();
+        grid.refresh_cache();
+        grid
+    }
 }
 
 impl<'a, T: StoredItemType> Add for Grid<'a, T> {
@@ -209,6 +931,80 @@ impl<'a, T: StoredItemType> Add for Grid<'a, T> {
     }
 }
 
+#[derive(Debug, Default)]
 pub struct GridNetwork<'a> {
-    item_grid: Grid<'a, Item>
+    pub item_grid: Grid<'a, Item>,
+
+    /// Opt-in power simulation, see `enable_energy`
+    pub energy: Option<Rc<RefCell<EnergyGrid>>>,
+
+    /// Definitions behind key ids, see `resolve`
+    pub keys: Option<&'a KeyRegistry>,
+}
+
+impl<'a> GridNetwork<'a> {
+    pub fn id(&self) -> GridId {
+        self.item_grid.id
+    }
+
+    /// Makes every grid of the network draw from `energy`
+    pub fn enable_energy(&mut self, energy: EnergyGrid) -> Rc<RefCell<EnergyGrid>> {
+        let energy = Rc::new(RefCell::new(energy));
+        self.item_grid.energy = Some(energy.clone());
+        self.energy = Some(energy.clone());
+        energy
+    }
+
+    pub fn idle_drain(&self) -> f64 {
+        self.item_grid.idle_drain()
+    }
+
+    /// Definition of any key id, `None` without a registry or for unknown ids
+    pub fn resolve(&self, id: KeyId) -> Option<Key<'a>> {
+        self.keys?.get(id)
+    }
+
+    pub fn resolve_name(&self, stored_type: StoredItemTypes, name: &str) -> Option<Key<'a>> {
+        self.keys?.get_by_name(stored_type, name)
+    }
+
+    /// Pays storage and `device_drain` for one tick, returns whether the network stays online
+    pub fn tick_energy(&mut self, device_drain: f64) -> bool {
+        let idle_drain = self.idle_drain() + device_drain;
+        match &self.energy {
+            Some(energy) => energy.borrow_mut().tick(idle_drain),
+            None => true
+        }
+    }
+
+    pub fn union(&mut self, other: Self) {
+        self.keys = self.keys.or(other.keys);
+        match (&self.energy, other.energy) {
+            (Some(energy), Some(other_energy)) => {
+                let other_energy = other_energy.borrow().clone();
+                energy.borrow_mut().merge(other_energy);
+            }
+            (None, Some(other_energy)) => {
+                let other_energy = other_energy.borrow().clone();
+                self.enable_energy(other_energy);
+            }
+            _ => {}
+        }
+        let energy = self.item_grid.energy.take();
+        self.item_grid.union(other.item_grid);
+        self.item_grid.energy = energy;
+    }
+
+    /// The split-off part starts with an empty buffer, energy cells and acceptors stay here
+    pub fn split_off_hosts(&mut self, ids: &BTreeSet<HostId>) -> Self {
+        let mut network = GridNetwork {
+            item_grid: self.item_grid.split_off_hosts(ids),
+            energy: None,
+            keys: self.keys
+        };
+        if self.energy.is_some() {
+            network.enable_energy(EnergyGrid::new());
+        }
+        network
+    }
 }
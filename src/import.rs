@@ -0,0 +1,333 @@
+use crate::datapack::DataPackTags;
+use crate::fluid::{Fluid, FluidProperties};
+use crate::item::Item;
+use crate::registry::{KeyRegistry, RegistryError, RegistryId, RegistryKey};
+use crate::storage::{StoredItemType, StoredItemTypes};
+use crate::tag::{TagEntry, TagName};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Separates the tags of a CSV record
+const CSV_TAG_SEPARATOR: char = ';';
+/// CSV columns read as numbers or flags, everything else is text
+const CSV_INTEGER_COLUMNS: [&str; 6] = ["damage", "max_stack_size", "max_damage", "temperature", "viscosity", "density"];
+const CSV_FLAG_COLUMNS: [&str; 1] = ["gaseous"];
+
+/// Adds a key to the registry of its type
+type Register<K> = fn(&mut KeyRegistry, K) -> Result<RegistryId<K>, RegistryError<K>>;
+
+fn default_stack_size() -> i32 {
+    64
+}
+
+#[derive(Deserialize)]
+struct ItemRecord {
+    id: String,
+    #[serde(default)]
+    damage: i32,
+    #[serde(default = "default_stack_size")]
+    max_stack_size: i32,
+    #[serde(default)]
+    max_damage: i32,
+    #[serde(default)]
+    display_name: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct FluidRecord {
+    id: String,
+    #[serde(flatten)]
+    properties: FluidProperties,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// `{"items": [...], "fluids": [...]}` or just the item list
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonDump {
+    Items(Vec<Value>),
+    Keys {
+        #[serde(default)]
+        items: Vec<Value>,
+        #[serde(default)]
+        fluids: Vec<Value>
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Io { path: PathBuf, error: io::Error },
+    /// The dump as a whole is not valid JSON, single bad records only end up in the report
+    Json(serde_json::Error),
+    /// Line is 1-based
+    Csv { line: usize, reason: String },
+    /// Neither `.json` nor `.csv`
+    UnknownFormat(PathBuf)
+}
+
+/// Where a record sits in its dump, 1-based
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ImportRecord {
+    /// Position in the item list of a JSON dump
+    Item(usize),
+    /// Position in the fluid list of a JSON dump
+    Fluid(usize),
+    /// Line of a CSV dump, items and fluids alike
+    Line(usize)
+}
+
+/// Record that was not imported
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportIssue {
+    pub record: ImportRecord,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ImportReport {
+    pub items: usize,
+    pub fluids: usize,
+    /// Registry names seen again after their first definition, which is kept, with how often
+    pub duplicates: BTreeMap<String, usize>,
+    pub invalid: Vec<ImportIssue>,
+}
+
+impl ImportReport {
+    pub fn is_clean(&self) -> bool {
+        self.duplicates.is_empty() && self.invalid.is_empty()
+    }
+}
+
+/// Fills the registries and tags of a game from item dumps of a modpack
+pub struct Importer<'r> {
+    pub keys: &'r mut KeyRegistry,
+    pub tags: &'r mut DataPackTags,
+    pub report: ImportReport,
+}
+
+impl<'r> Importer<'r> {
+    pub fn new(keys: &'r mut KeyRegistry, tags: &'r mut DataPackTags) -> Self {
+        Importer {
+            keys,
+            tags,
+            report: ImportReport::default()
+        }
+    }
+
+    /// Imports a `.json` or `.csv` dump
+    pub fn import_file(&mut self, path: &Path) -> Result<(), ImportError> {
+        let text = fs::read_to_string(path).map_err(|error| ImportError::Io { path: path.to_path_buf(), error })?;
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("json") => self.import_json(&text),
+            Some("csv") => self.import_csv(&text),
+            _ => Err(ImportError::UnknownFormat(path.to_path_buf()))
+        }
+    }
+
+    pub fn import_json(&mut self, text: &str) -> Result<(), ImportError> {
+        let (items, fluids) = match serde_json::from_str(text).map_err(ImportError::Json)? {
+            JsonDump::Items(items) => (items, vec![]),
+            JsonDump::Keys { items, fluids } => (items, fluids)
+        };
+        for (i, value) in items.into_iter().enumerate() {
+            self.import_item(ImportRecord::Item(i + 1), value);
+        }
+        for (i, value) in fluids.into_iter().enumerate() {
+            self.import_fluid(ImportRecord::Fluid(i + 1), value);
+        }
+        Ok(())
+    }
+
+    /// The header names the columns, they are the fields of the JSON records. An optional `kind`
+    /// column tells `item` and `fluid` rows apart, tags are separated by `;`.
+    pub fn import_csv(&mut self, text: &str) -> Result<(), ImportError> {
+        let mut rows = parse_csv(text)?.into_iter();
+        let header = match rows.next() {
+            Some((_, header)) => header,
+            None => return Ok(())
+        };
+        for (line, fields) in rows {
+            let line = ImportRecord::Line(line);
+            if fields.len() != header.len() {
+                self.invalid(line, format!("expected {} columns, found {}", header.len(), fields.len()));
+                continue;
+            }
+            let mut record = Map::new();
+            let mut kind = "item".to_string();
+            let mut bad_column = None;
+            for (column, field) in header.iter().zip(fields) {
+                let field = field.trim();
+                if field.is_empty() {
+                    continue;
+                }
+                let value = match column.as_str() {
+                    "kind" => {
+                        kind = field.to_string();
+                        continue;
+                    }
+                    "tags" => Value::from(field.split(CSV_TAG_SEPARATOR).map(|x| x.trim()).filter(|x| !x.is_empty()).collect::<Vec<&str>>()),
+                    x if CSV_INTEGER_COLUMNS.contains(&x) => match field.parse::<i64>() {
+                        Ok(number) => Value::from(number),
+                        Err(_) => {
+                            bad_column = Some(column);
+                            break;
+                        }
+                    },
+                    x if CSV_FLAG_COLUMNS.contains(&x) => match field.parse::<bool>() {
+                        Ok(flag) => Value::from(flag),
+                        Err(_) => {
+                            bad_column = Some(column);
+                            break;
+                        }
+                    },
+                    _ => Value::from(field)
+                };
+                record.insert(column.clone(), value);
+            }
+            if let Some(column) = bad_column {
+                self.invalid(line, format!("bad value for {}", column));
+                continue;
+            }
+            match kind.as_str() {
+                "item" => self.import_item(line, Value::Object(record)),
+                "fluid" => self.import_fluid(line, Value::Object(record)),
+                _ => self.invalid(line, format!("unknown kind {}", kind))
+            }
+        }
+        Ok(())
+    }
+
+    fn invalid(&mut self, record: ImportRecord, reason: String) {
+        self.report.invalid.push(ImportIssue { record, reason });
+    }
+
+    fn import_item(&mut self, record: ImportRecord, value: Value) {
+        let parsed: ItemRecord = match serde_json::from_value(value) {
+            Ok(parsed) => parsed,
+            Err(error) => return self.invalid(record, error.to_string())
+        };
+        let problem = if parsed.damage < 0 {
+            Some("negative damage")
+        } else if parsed.max_stack_size < 1 {
+            Some("max stack size below 1")
+        } else if parsed.max_damage < 0 {
+            Some("negative max damage")
+        } else if parsed.max_damage > 0 && parsed.max_stack_size > 1 {
+            Some("items that take damage do not stack")
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            return self.invalid(record, format!("{}: {}", parsed.id, problem));
+        }
+        let mut item = Item::new(&parsed.id);
+        item.damage = parsed.damage;
+        item.max_stack_size = parsed.max_stack_size;
+        item.max_damage = parsed.max_damage;
+        item.display_name = parsed.display_name;
+        if self.register(record, &parsed.id, item, &parsed.tags, |keys, x| keys.items.register(x)) {
+            self.report.items += 1;
+        }
+    }
+
+    fn import_fluid(&mut self, record: ImportRecord, value: Value) {
+        let parsed: FluidRecord = match serde_json::from_value(value) {
+            Ok(parsed) => parsed,
+            Err(error) => return self.invalid(record, error.to_string())
+        };
+        if parsed.properties.viscosity <= 0 {
+            return self.invalid(record, format!("{}: viscosity must be positive", parsed.id));
+        }
+        let fluid = Fluid::new(&parsed.id).with_properties(parsed.properties);
+        if self.register(record, &parsed.id, fluid, &parsed.tags, |keys, x| keys.fluids.register(x)) {
+            self.report.fluids += 1;
+        }
+    }
+
+    /// Validates the id and tags, then registers the key and adds it to its tags. Returns whether it was new.
+    fn register<K>(&mut self, record: ImportRecord, id: &str, key: K, tags: &[String], register: Register<K>) -> bool
+        where K: RegistryKey + StoredItemType {
+        let name = key.registry_name();
+        if !is_namespaced_id(id) {
+            self.invalid(record, format!("{}: not a namespaced id", id));
+            return false;
+        }
+        let mut tag_names = vec![];
+        for tag in tags {
+            match TagName::parse(tag) {
+                Ok(tag) => tag_names.push(tag),
+                Err(_) => {
+                    self.invalid(record, format!("{}: bad tag {}", name, tag));
+                    return false;
+                }
+            }
+        }
+        match register(self.keys, key) {
+            Ok(_) => {}
+            Err(RegistryError::Duplicate { name, .. }) => {
+                *self.report.duplicates.entry(name).or_default() += 1;
+                return false;
+            }
+            Err(error) => {
+                self.invalid(record, error.to_string());
+                return false;
+            }
+        }
+        let registry = match K::stored_type() {
+            StoredItemTypes::Item => &mut self.tags.items,
+            StoredItemTypes::Fluid => &mut self.tags.fluids
+        };
+        for tag in tag_names {
+            registry.extend(tag, vec![TagEntry::Key(name.clone())]);
+        }
+        true
+    }
+}
+
+/// `namespace:path`, both parts spelled like in tag names but without the `#` or a default namespace
+fn is_namespaced_id(id: &str) -> bool {
+    let (namespace, path) = match id.split_once(':') {
+        Some(parts) => parts,
+        None => return false
+    };
+    !namespace.is_empty() && namespace.chars().all(|x| matches!(x, 'a'..='z' | '0'..='9' | '_' | '.' | '-'))
+        && !path.is_empty() && path.chars().all(|x| matches!(x, 'a'..='z' | '0'..='9' | '_' | '.' | '-' | '/'))
+}
+
+/// Rows with their 1-based line, blank lines skipped. Fields may be quoted, `""` is a quote inside them.
+fn parse_csv(text: &str) -> Result<Vec<(usize, Vec<String>)>, ImportError> {
+    let mut rows = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = vec![];
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, quoted) {
+                ('"', true) if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                ('"', _) => quoted = !quoted,
+                (',', false) => fields.push(std::mem::take(&mut field)),
+                _ => field.push(c)
+            }
+        }
+        if quoted {
+            return Err(ImportError::Csv { line: i + 1, reason: "unterminated quote".to_string() });
+        }
+        fields.push(field);
+        rows.push((i + 1, fields));
+    }
+    Ok(rows)
+}
@@ -0,0 +1,148 @@
+use crate::grid::{Grid, GridId};
+use crate::inventory::Inventory;
+use crate::storage::{StoredItem, StoredItemType};
+use std::cmp::min;
+use std::rc::Rc;
+use std::cell::RefCell;
+use serde::Serialize;
+
+pub const INTERFACE_SLOTS: usize = 9;
+
+/// Keeps configured amounts of items in its slots for adjacent machines, anything else goes back to the grid.
+/// Attached to a grid through `Grid::attach_interface` its slots also count as storage.
+#[derive(Debug, Serialize)]
+pub struct Interface<'a, T: StoredItemType> {
+    /// Identifies the interface as a storage bus target
+    pub id: GridId,
+    /// "Keep N of X" for the slot at the same index
+    config: Vec<Option<StoredItem<'a, T>>>,
+    pub inventory: Inventory<'a, T>,
+}
+
+impl<'a, T: StoredItemType> Default for Interface<'a, T> {
+    fn default() -> Self {
+        Interface {
+            id: GridId::next(),
+            config: vec![None; INTERFACE_SLOTS],
+            inventory: Inventory::new(INTERFACE_SLOTS)
+        }
+    }
+}
+
+impl<'a, T: StoredItemType> Interface<'a, T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn config(&self) -> &[Option<StoredItem<'a, T>>] {
+        &self.config
+    }
+
+    /// Sets what a slot keeps in stock, at most one stack. Gives the stock back if the slot does not exist.
+    pub fn configure(&mut self, slot: usize, stock: Option<StoredItem<'a, T>>) -> Result<(), Option<StoredItem<'a, T>>> {
+        match self.config.get_mut(slot) {
+            Some(x) => {
+                *x = stock.map(|x| StoredItem::new(x.item, min(x.count, x.item.stack_limit())));
+                Ok(())
+            }
+            None => Err(stock)
+        }
+    }
+
+    /// Items short of the configured amount, negative for surplus to return. The whole stack
+    /// is surplus when the slot holds something it is not configured for. `None` for slots that do not exist.
+    pub fn demand(&self, slot: usize) -> Option<StoredItem<'a, T>> {
+        let demand = match (self.config.get(slot)?, self.inventory.slots.get(slot)?) {
+            (Some(stock), None) => Some(stock.clone()),
+            (Some(stock), Some(stack)) if stock.item == stack.item => Some(StoredItem::new(stock.item, stock.count - stack.count)),
+            (_, Some(stack)) => Some(StoredItem::new(stack.item, -stack.count)),
+            (None, None) => None
+        };
+        demand.filter(|x| x.count != 0)
+    }
+
+    /// Changes a slot by `delta`, the slot must hold `item` or be empty
+    fn adjust(&mut self, slot: usize, item: &'a T, delta: i32) {
+        let count = self.inventory.slots[slot].as_ref().map_or(0, |x| x.count) + delta;
+        self.inventory.slots[slot] = if count > 0 { Some(StoredItem::new(item, count)) } else { None };
+    }
+
+    /// Room left in a slot configured for `item`
+    fn room(&self, slot: usize, item: &T) -> i32 {
+        match (&self.config[slot], self.demand(slot)) {
+            (Some(stock), Some(demand)) if stock.item == item => demand.count.max(0),
+            _ => 0
+        }
+    }
+
+    /// Storage view: only fills configured slots up to their stock, returns what did not fit
+    pub fn insert(&mut self, item: StoredItem<'a, T>) -> i32 {
+        let mut count = item.count;
+        for slot in 0..self.config.len() {
+            let moved = min(count, self.room(slot, item.item));
+            if moved > 0 {
+                self.adjust(slot, item.item, moved);
+                count -= moved;
+            }
+        }
+        count
+    }
+
+    /// Dry run of `insert`, returns what would be left over
+    pub fn simulate_insert(&self, item: &StoredItem<'a, T>) -> i32 {
+        (0..self.config.len()).fold(item.count, |count, slot| count - min(count, self.room(slot, item.item)))
+    }
+
+    /// Storage view: every slot can be drained, the interface restocks on its next tick
+    pub fn extract(&mut self, item: &'a T, count: i32) -> i32 {
+        self.inventory.extract(item, count, false)
+    }
+
+    /// Moves up to `budget` items between the slots and `grid`, returns how many were moved.
+    /// Items are never pulled from or returned into the interface itself when it is attached to `grid`.
+    /// Caches of other grids the interface is attached to catch up on their next refresh.
+    pub fn stock(this: &Rc<RefCell<Self>>, grid: &mut Grid<'a, T>, budget: i32) -> i32 {
+        let id = this.borrow().id;
+        let slots = this.borrow().config.len();
+        let mut moved = 0;
+        for slot in 0..slots {
+            if moved == budget {
+                break;
+            }
+            let demand = match this.borrow().demand(slot) {
+                Some(demand) => demand,
+                None => continue
+            };
+            let key = demand.item;
+            // Slots are changed before the grid so its cache refresh sees a consistent total
+            if demand.count > 0 {
+                let wanted = min(demand.count, budget - moved);
+                let available = grid.simulate_take_bypassing(&StoredItem::new(key, wanted), id);
+                if available == 0 {
+                    continue;
+                }
+                this.borrow_mut().adjust(slot, key, available);
+                let taken = grid.take_bypassing(StoredItem::new(key, available), id);
+                if taken < available {
+                    this.borrow_mut().adjust(slot, key, taken - available);
+                    grid.refresh_cache();
+                }
+                moved += taken;
+            } else {
+                let surplus = min(-demand.count, budget - moved);
+                let accepted = surplus - grid.simulate_insert_bypassing(&StoredItem::new(key, surplus), id);
+                if accepted == 0 {
+                    continue;
+                }
+                this.borrow_mut().adjust(slot, key, -accepted);
+                let left = grid.insert_bypassing(StoredItem::new(key, accepted), id);
+                if left > 0 {
+                    this.borrow_mut().adjust(slot, key, left);
+                    grid.refresh_cache();
+                }
+                moved += accepted - left;
+            }
+        }
+        moved
+    }
+}
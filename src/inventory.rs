@@ -0,0 +1,86 @@
+use crate::storage::{StoredItem, StoredItemType};
+use std::cmp::min;
+use serde::Serialize;
+
+/// Slot based container outside of any grid, e.g. a chest or a machine buffer.
+/// With `simulate` set, mutating methods report their result without changing anything.
+#[derive(Debug, Clone, Serialize)]
+pub struct Inventory<'a, T: StoredItemType> {
+    pub slots: Vec<Option<StoredItem<'a, T>>>,
+}
+
+impl<'a, T: StoredItemType> Inventory<'a, T> {
+    pub fn new(size: usize) -> Self {
+        Inventory {
+            slots: vec![None; size]
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(|x| x.is_none())
+    }
+
+    pub fn stacks(&self) -> impl Iterator<Item = &StoredItem<'a, T>> {
+        self.slots.iter().flatten()
+    }
+
+    pub fn count(&self, item: &T) -> i32 {
+        self.stacks().filter(|x| x.item == item).map(|x| x.count).sum()
+    }
+
+    /// Tops up existing stacks first, then fills empty slots. Returns what did not fit.
+    pub fn insert(&mut self, item: StoredItem<'a, T>, simulate: bool) -> i32 {
+        let limit = item.item.stack_limit();
+        let mut count = item.count;
+        for stack in self.slots.iter_mut().flatten().filter(|x| x.item == item.item) {
+            if count == 0 {
+                break;
+            }
+            let moved = min(count, limit - stack.count).max(0);
+            if !simulate {
+                stack.count += moved;
+            }
+            count -= moved;
+        }
+        for slot in self.slots.iter_mut().filter(|x| x.is_none()) {
+            if count == 0 {
+                break;
+            }
+            let moved = min(count, limit);
+            if !simulate {
+                *slot = Some(StoredItem::new(item.item, moved));
+            }
+            count -= moved;
+        }
+        count
+    }
+
+    /// Removes up to `count` of `item` across all slots, returns how many were removed
+    pub fn extract(&mut self, item: &'a T, count: i32, simulate: bool) -> i32 {
+        let mut extracted = 0;
+        for slot in self.slots.iter_mut() {
+            if extracted >= count {
+                break;
+            }
+            let emptied = match slot.as_mut() {
+                Some(stack) if stack.item == item => {
+                    let moved = min(count - extracted, stack.count);
+                    extracted += moved;
+                    if !simulate {
+                        stack.count -= moved;
+                    }
+                    stack.count == 0
+                }
+                _ => false
+            };
+            if emptied {
+                *slot = None;
+            }
+        }
+        extracted
+    }
+}
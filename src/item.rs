@@ -1,19 +1,18 @@
 use nbt::Blob;
-use std::ops::Add;
 use std::cmp::Ordering;
-use std::hash::Hash;
 use crate::storage::{StoredItemType, StoredItemTypes};
-use std::convert::TryInto;
 use serde::{Serialize, Serializer};
-use serde::ser::{Error};
 
 /// Representing a "definition stack"
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct Item {
     pub id: String,
     pub damage: i32,
     pub max_stack_size: i32,
     pub tag: nbt::Blob,
+    /// 0 for items that do not take damage
+    pub max_damage: i32,
+    pub display_name: Option<String>,
 }
 
 impl PartialOrd for Item {
@@ -40,6 +39,10 @@ impl StoredItemType for Item {
     fn stored_type() -> StoredItemTypes {
         StoredItemTypes::Item
     }
+
+    fn stack_limit(&self) -> i32 {
+        self.max_stack_size
+    }
 }
 
 impl Item {
@@ -48,7 +51,9 @@ impl Item {
             id: id.to_string(),
             damage: 0,
             max_stack_size: 64,
-            tag: Blob::default()
+            tag: Blob::default(),
+            max_damage: 0,
+            display_name: None
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,201 @@
+use crate::crafting::{Pattern, PatternId};
+use crate::grid::Grid;
+use crate::inventory::Inventory;
+use crate::simulation::SimRng;
+use crate::storage::{StoredItem, StoredItemType};
+use std::cmp::min;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+use std::cell::RefCell;
+use serde::Serialize;
+
+/// Extra output a recipe yields only some of the time
+#[derive(Debug, Clone, Serialize)]
+pub struct Byproduct<'a, T: StoredItemType> {
+    pub item: StoredItem<'a, T>,
+    /// Between 0.0 and 1.0
+    pub chance: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MachineRecipe<'a, T: StoredItemType> {
+    pub inputs: Vec<StoredItem<'a, T>>,
+    pub outputs: Vec<StoredItem<'a, T>>,
+    pub byproducts: Vec<Byproduct<'a, T>>,
+    pub duration: u64,
+}
+
+impl<'a, T: StoredItemType> MachineRecipe<'a, T> {
+    pub fn new(inputs: Vec<StoredItem<'a, T>>, outputs: Vec<StoredItem<'a, T>>, duration: u64) -> Self {
+        MachineRecipe {
+            inputs,
+            outputs,
+            byproducts: vec![],
+            duration
+        }
+    }
+
+    pub fn with_byproduct(mut self, item: StoredItem<'a, T>, chance: f64) -> Self {
+        self.byproducts.push(Byproduct { item, chance });
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Running<'a, T: StoredItemType> {
+    /// Rolled when the recipe starts
+    outputs: Vec<StoredItem<'a, T>>,
+    done_at: u64,
+}
+
+/// Stand-in for an external machine: takes inputs into one inventory, works on one recipe
+/// at a time and puts the results into another
+#[derive(Debug, Clone, Serialize)]
+pub struct Machine<'a, T: StoredItemType> {
+    pub recipes: Vec<MachineRecipe<'a, T>>,
+    pub input: Inventory<'a, T>,
+    pub output: Inventory<'a, T>,
+    running: Option<Running<'a, T>>,
+}
+
+impl<'a, T: StoredItemType> Machine<'a, T> {
+    pub fn new(recipes: Vec<MachineRecipe<'a, T>>, slots: usize) -> Self {
+        Machine {
+            recipes,
+            input: Inventory::new(slots),
+            output: Inventory::new(slots),
+            running: None
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.running.is_none()
+    }
+
+    /// Longest recipe, used for estimates
+    pub fn duration(&self) -> u64 {
+        self.recipes.iter().map(|x| x.duration).max().unwrap_or(0)
+    }
+
+    /// Finishes the current recipe once its time is up and there is room for the results,
+    /// then starts the first recipe whose inputs are all there. Returns whether one was started.
+    pub fn tick(&mut self, now: u64, rng: &mut SimRng) -> bool {
+        if let Some(running) = &self.running {
+            let mut output = self.output.clone();
+            if now < running.done_at || running.outputs.iter().any(|x| output.insert(x.clone(), false) > 0) {
+                return false;
+            }
+            self.output = output;
+            self.running = None;
+        }
+        let recipe = match self.recipes.iter().find(|recipe| recipe.inputs.iter().all(|x| self.input.count(x.item) >= x.count)) {
+            Some(recipe) => recipe,
+            None => return false
+        };
+        for input in recipe.inputs.iter() {
+            self.input.extract(input.item, input.count, false);
+        }
+        let mut outputs = recipe.outputs.clone();
+        outputs.extend(recipe.byproducts.iter().filter(|x| rng.chance(x.chance)).map(|x| x.item.clone()));
+        self.running = Some(Running {
+            outputs,
+            done_at: now + recipe.duration
+        });
+        true
+    }
+}
+
+/// Runs processing patterns by pushing their inputs into a machine. Outputs owed to crafting jobs
+/// wait in the machine until collected, everything else goes back to the grid.
+#[derive(Debug, Serialize)]
+pub struct PatternProvider<'a, T: StoredItemType> {
+    pub patterns: BTreeSet<PatternId>,
+    #[serde(skip)]
+    pub machine: Rc<RefCell<Machine<'a, T>>>,
+    owed: BTreeMap<&'a T, i32>,
+}
+
+impl<'a, T: StoredItemType> PatternProvider<'a, T> {
+    pub fn new(machine: Rc<RefCell<Machine<'a, T>>>, patterns: Vec<PatternId>) -> Self {
+        PatternProvider {
+            patterns: patterns.into_iter().collect(),
+            machine,
+            owed: BTreeMap::new()
+        }
+    }
+
+    pub fn provides(&self, id: PatternId) -> bool {
+        self.patterns.contains(&id)
+    }
+
+    /// Whether the inputs of one run of `pattern` fit into the machine
+    pub fn can_push(&self, pattern: &Pattern<'a, T>) -> bool {
+        let mut input = self.machine.borrow().input.clone();
+        pattern.inputs.iter().all(|x| input.insert(x.clone(), false) == 0)
+    }
+
+    /// Pushes the inputs of one run of `pattern` into the machine, only if all of them fit
+    pub fn push(&mut self, pattern: &Pattern<'a, T>) -> bool {
+        if !self.can_push(pattern) {
+            return false;
+        }
+        let mut machine = self.machine.borrow_mut();
+        for input in pattern.inputs.iter() {
+            machine.input.insert(input.clone(), false);
+        }
+        for output in pattern.outputs.iter() {
+            *self.owed.entry(output.item).or_default() += output.count;
+        }
+        true
+    }
+
+    /// Takes the outputs of one run of `pattern` out of the machine once all of them are there
+    pub fn collect(&mut self, pattern: &Pattern<'a, T>) -> bool {
+        let mut machine = self.machine.borrow_mut();
+        if pattern.outputs.iter().any(|x| machine.output.count(x.item) < x.count) {
+            return false;
+        }
+        for output in pattern.outputs.iter() {
+            machine.output.extract(output.item, output.count, false);
+        }
+        drop(machine);
+        self.release(pattern);
+        true
+    }
+
+    /// Gives up on the outputs of one run of `pattern`, they go to the grid once the machine is done
+    pub fn release(&mut self, pattern: &Pattern<'a, T>) {
+        for output in pattern.outputs.iter() {
+            if let Some(owed) = self.owed.get_mut(output.item) {
+                *owed -= output.count;
+                if *owed <= 0 {
+                    self.owed.remove(output.item);
+                }
+            }
+        }
+    }
+
+    /// Moves up to `limit` machine outputs nobody is waiting for into `grid`, returns how many were moved
+    pub fn return_surplus(&mut self, grid: &mut Grid<'a, T>, limit: i32) -> i32 {
+        let mut machine = self.machine.borrow_mut();
+        let keys: BTreeSet<&'a T> = machine.output.stacks().map(|x| x.item).collect();
+        let mut moved = 0;
+        for key in keys {
+            let surplus = min(machine.output.count(key) - self.owed.get(key).copied().unwrap_or(0), limit - moved);
+            if surplus <= 0 {
+                continue;
+            }
+            let accepted = surplus - grid.simulate_insert(&StoredItem::new(key, surplus));
+            if accepted <= 0 {
+                continue;
+            }
+            machine.output.extract(key, accepted, false);
+            let left = grid.insert(StoredItem::new(key, accepted));
+            if left > 0 {
+                machine.output.insert(StoredItem::new(key, left), false);
+            }
+            moved += accepted - left;
+        }
+        moved
+    }
+}
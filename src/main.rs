@@ -1,14 +1,36 @@
 use crate::registry::ItemRegistry;
 use crate::item::{Item};
-use crate::storage::{StorageCell, CELL_TYPE_1K, StoredItem, CELL_TYPE_64K};
+use crate::storage::{StorageCell, StoredItem, CELL_TYPE_64K};
 use std::time::Instant;
-use std::slice::Iter;
 
 #[cfg(test)]
+#[allow(unused_variables, unused_must_use, clippy::needless_range_loop, clippy::useless_conversion)]
 mod test {
-    use crate::storage::{StorageCell, CELL_TYPE_1K, StoredItem, CELL_TYPE_16K};
+    use crate::storage::{StorageCell, CELL_TYPE_1K, StoredItem, CELL_TYPE_16K, INFINITE_COUNT, AccessMode, StoredItemTypes};
     use crate::item::{Item};
-    use crate::grid::Grid;
+    use crate::grid::{Grid, CellRef, GridError};
+    use crate::energy::EnergyGrid;
+    use crate::watch::{WatchFilter, WatchSink};
+    use crate::emitter::{LevelEmitter, EmitterMode, EmitterEdge};
+    use crate::drive::CellHost;
+    use crate::inventory::Inventory;
+    use crate::interface::Interface;
+    use crate::crafting::{PatternBook, Pattern, CraftingStep, CraftingError, Substitution};
+    use crate::tag::{Tag, TagName, TagEntry, TagError, TagRegistry};
+    use crate::datapack::{DataPackTags, DataPackError};
+    use crate::import::{Importer, ImportError, ImportIssue, ImportRecord};
+    use crate::fluid::{Fluid, FluidProperties};
+    use crate::registry::{ItemRegistry, FluidRegistry, KeyRegistry, KeyId, Key, RegistryError, RegistryKey};
+    use crate::cpu::{CraftingCpu, CpuError, JobState, CRAFTING_STORAGE_1K};
+    use crate::machine::{Machine, MachineRecipe, PatternProvider};
+    use crate::bus::{ImportBus, ExportBus, BusConfig, UpgradeCard};
+    use crate::simulation::{Simulation, Device, TickContext, Attached, SimEventKind};
+    use crate::topology::{Topology, BlockPos, DeviceKind, CableKind};
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use std::collections::{BTreeSet, HashMap};
+    use std::fs;
+    use std::path::Path;
 
     #[test]
     fn test_free_space() {
@@ -77,11 +99,1185 @@ mod test {
         assert_eq!(grid.stored_items_cache.len(), items.len());
         assert_eq!(grid.take(StoredItem::new(&items[0], 5)), 5);
     }
+
+    #[test]
+    fn test_sub_grid() {
+        let stone = Item::new("minecraft:stone");
+        let sub_grid = Rc::new(RefCell::new(Grid::default()));
+        sub_grid.borrow_mut().insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        sub_grid.borrow_mut().insert(StoredItem::new(&stone, 100));
+
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        grid.attach_grid(sub_grid.clone());
+        assert_eq!(grid.stored_items_cache[&stone].count, 100);
+
+        // Overflow lands in the sub-network
+        assert_eq!(grid.insert(StoredItem::new(&stone, 8000)), 0);
+        assert_eq!(grid.stored_items_cache[&stone].count, 8100);
+        assert_eq!(sub_grid.borrow().stored_items_cache[&stone].count, 8100 - grid.storage_cells[0].stored_items_count);
+
+        assert_eq!(grid.take(StoredItem::new(&stone, 8100)), 8100);
+        assert!(grid.stored_items_cache.is_empty());
+        assert!(sub_grid.borrow().stored_items_cache.is_empty());
+    }
+
+    #[test]
+    fn test_sub_grid_loop() {
+        let stone = Item::new("minecraft:stone");
+        let a = Rc::new(RefCell::new(Grid::default()));
+        let b = Rc::new(RefCell::new(Grid::default()));
+        a.borrow_mut().insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        b.borrow_mut().insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        a.borrow_mut().insert(StoredItem::new(&stone, 10));
+        b.borrow_mut().insert(StoredItem::new(&stone, 20));
+
+        a.borrow_mut().attach_grid(b.clone());
+        b.borrow_mut().attach_grid(a.clone());
+        a.borrow_mut().attach_grid(a.clone());
+        a.borrow_mut().refresh_cache();
+        assert_eq!(a.borrow().stored_items_cache[&stone].count, 30);
+        assert_eq!(b.borrow().stored_items_cache[&stone].count, 30);
+
+        // Nothing fits anywhere, the request must come back instead of bouncing between grids
+        let huge = 1_000_000;
+        let remaining = a.borrow_mut().insert(StoredItem::new(&stone, huge));
+        assert!(remaining > 0);
+        let stored = a.borrow().stored_items_cache[&stone].count;
+        assert_eq!(stored, 30 + huge - remaining);
+        assert_eq!(a.borrow_mut().take(StoredItem::new(&stone, huge)), stored);
+    }
+
+    #[test]
+    fn test_sort_keeps_items_local() {
+        let stone = Item::new("minecraft:stone");
+        let dirt = Item::new("minecraft:dirt");
+        let sub_grid = Rc::new(RefCell::new(Grid::default()));
+        sub_grid.borrow_mut().insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        grid.insert(StoredItem::new(&dirt, 50));
+        grid.attach_grid(sub_grid.clone());
+        // Dirt no longer fits the partition, sorting must neither push it into the sub-network nor drop it
+        grid.storage_cells[0].partition.insert(&stone);
+        grid.sort();
+        assert_eq!(grid.storage_cells[0].stored_items[&dirt].count, 50);
+        assert_eq!(grid.stored_items_cache[&dirt].count, 50);
+        assert!(sub_grid.borrow().stored_items_cache.is_empty());
+    }
+
+    #[test]
+    fn test_sort_read_only_cell() {
+        let stone = Item::new("minecraft:stone");
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        grid.insert(StoredItem::new(&stone, 100));
+        let full = grid.storage_cells.iter().position(|x| x.stored_items_count > 0).unwrap();
+        grid.storage_cells[full].config.access = AccessMode::Read;
+        let read_only = grid.storage_cells[full].id;
+        grid.sort();
+        // Nothing could be put back into a read-only cell, so it is left alone
+        let cell = grid.storage_cells.iter().find(|x| x.id == read_only).unwrap();
+        assert_eq!(cell.stored_items[&stone].count, 100);
+        assert_eq!(grid.storage_cells.iter().map(|x| x.stored_items_count).sum::<i32>(), 100);
+        assert_eq!(grid.stored_items_cache[&stone].count, 100);
+    }
+
+    #[test]
+    fn test_creative_cell() {
+        let stone = Item::new("minecraft:stone");
+        let dirt = Item::new("minecraft:dirt");
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::creative(vec![&stone]));
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        assert_eq!(grid.stored_items_cache[&stone].count, INFINITE_COUNT);
+
+        assert_eq!(grid.take(StoredItem::new(&stone, 1_000_000)), 1_000_000);
+        assert_eq!(grid.stored_items_cache[&stone].count, INFINITE_COUNT);
+
+        // Partitioned items are voided, anything else goes to regular cells
+        assert_eq!(grid.insert(StoredItem::new(&stone, 5000)), 0);
+        assert_eq!(grid.insert(StoredItem::new(&dirt, 10)), 0);
+        assert_eq!(grid.storage_cells.iter().map(|x| x.stored_items.len()).sum::<usize>(), 2);
+        assert_eq!(grid.stored_items_cache[&dirt].count, 10);
+
+        grid.sort();
+        assert_eq!(grid.stored_items_cache[&stone].count, INFINITE_COUNT);
+        assert_eq!(grid.stored_items_cache[&dirt].count, 10);
+
+        // Without a partition a creative cell neither provides nor voids anything
+        let mut empty = StorageCell::creative(vec![]);
+        assert!(!empty.accepts(&dirt));
+        assert_eq!(empty.get_free_space(&StoredItem::new(&dirt, 10)), 0);
+        assert_eq!(empty.insert(StoredItem::new(&dirt, 10)), 0);
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::creative(vec![]));
+        assert_eq!(grid.insert(StoredItem::new(&dirt, 10)), 10);
+        assert!(grid.stored_items_cache.is_empty());
+    }
+
+    #[test]
+    fn test_drive_hot_swap() {
+        let stone = Item::new("minecraft:stone");
+        let dirt = Item::new("minecraft:dirt");
+        let mut grid = Grid::default();
+        let drive = grid.add_host(CellHost::drive().with_priority(5));
+        let chest = grid.add_host(CellHost::chest());
+        assert_eq!(grid.hosts[&drive].slots.len(), 10);
+        assert!(grid.mount_cell(chest, 0, StorageCell::new(&CELL_TYPE_1K)).is_ok());
+        assert!(grid.mount_cell(chest, 0, StorageCell::new(&CELL_TYPE_1K)).is_err());
+        assert!(grid.mount_cell(drive, 3, StorageCell::new(&CELL_TYPE_1K)).is_ok());
+
+        // Lower priority is filled first, the chest gets the items
+        assert_eq!(grid.insert(StoredItem::new(&stone, 10)), 0);
+        assert_eq!(grid.stored_items_priority_cache[&stone], vec![CellRef::Hosted(chest, 0)]);
+
+        let mut cell = StorageCell::new(&CELL_TYPE_1K);
+        cell.insert(StoredItem::new(&dirt, 7));
+        cell.insert(StoredItem::new(&stone, 3));
+        assert!(grid.mount_cell(drive, 0, cell).is_ok());
+        assert_eq!(grid.stored_items_cache[&dirt].count, 7);
+        assert_eq!(grid.stored_items_cache[&stone].count, 13);
+        assert_eq!(grid.stored_items_priority_cache[&stone], vec![CellRef::Hosted(chest, 0), CellRef::Hosted(drive, 0)]);
+
+        grid.set_host_powered(drive, false);
+        assert!(!grid.stored_items_cache.contains_key(&dirt));
+        assert_eq!(grid.stored_items_cache[&stone].count, 10);
+        grid.set_host_powered(drive, true);
+        assert_eq!(grid.stored_items_cache[&stone].count, 13);
+
+        let cell = grid.eject_cell(drive, 0).unwrap();
+        assert_eq!(cell.stored_items_count, 10);
+        assert!(!grid.stored_items_cache.contains_key(&dirt));
+        assert_eq!(grid.stored_items_cache[&stone].count, 10);
+        assert_eq!(grid.take(StoredItem::new(&stone, 10)), 10);
+        assert!(grid.stored_items_cache.is_empty());
+    }
+
+    #[test]
+    fn test_cell_ids() {
+        let stone = Item::new("minecraft:stone");
+        let dirt = Item::new("minecraft:dirt");
+        let mut grid = Grid::default();
+        let mut cell = StorageCell::new(&CELL_TYPE_1K);
+        cell.config.priority = 10;
+        let high = grid.insert_storage_cell(cell);
+        let low = grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        let drive = grid.add_host(CellHost::drive());
+        let hosted = StorageCell::new(&CELL_TYPE_1K);
+        let hosted_id = hosted.id;
+        assert!(grid.mount_cell(drive, 2, hosted).is_ok());
+
+        grid.cell_by_id_mut(high).unwrap().insert(StoredItem::new(&stone, 4));
+        grid.cell_by_id_mut(low).unwrap().insert(StoredItem::new(&dirt, 6));
+        grid.sort();
+        assert_eq!(grid.locate_cell(hosted_id), Some(CellRef::Hosted(drive, 2)));
+        assert_eq!(grid.cell_by_id(high).unwrap().config.priority, 10);
+
+        // Removing the first loose cell must not break lookups of the ones behind it
+        let removed = grid.remove_storage_cell(low).unwrap();
+        assert_eq!(removed.id, low);
+        assert!(grid.cell_by_id(low).is_none());
+        let remaining: i32 = grid.stored_items_cache.values().map(|x| x.count).sum();
+        assert_eq!(remaining, 10 - removed.stored_items_count);
+        assert_eq!(grid.take(StoredItem::new(&stone, 4)) + grid.take(StoredItem::new(&dirt, 6)), remaining);
+
+        assert_eq!(grid.remove_storage_cell(hosted_id).unwrap().id, hosted_id);
+        assert!(grid.hosts[&drive].slots[2].is_none());
+        assert_eq!(grid.storage_cells.len(), 1);
+    }
+
+    #[test]
+    fn test_grid_split() {
+        let stone = Item::new("minecraft:stone");
+        let mut grid = Grid::default();
+        let kept = grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        let moved = grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        let drive = grid.add_host(CellHost::drive());
+        let mut hosted = StorageCell::new(&CELL_TYPE_1K);
+        hosted.insert(StoredItem::new(&stone, 100));
+        assert!(grid.mount_cell(drive, 0, hosted).is_ok());
+        assert!(grid.mount_cell(drive, 1, StorageCell::new(&CELL_TYPE_1K)).is_ok());
+        grid.cell_by_id_mut(kept).unwrap().insert(StoredItem::new(&stone, 1));
+        grid.cell_by_id_mut(moved).unwrap().insert(StoredItem::new(&stone, 10));
+        grid.refresh_cache();
+
+        let mut ids = BTreeSet::new();
+        ids.insert(moved);
+        let mut other = grid.split_off_cells(&ids);
+        assert_eq!(grid.stored_items_cache[&stone].count, 101);
+        assert_eq!(other.stored_items_cache[&stone].count, 10);
+
+        let mut hosts = BTreeSet::new();
+        hosts.insert(drive);
+        let drive_grid = grid.split_off_hosts(&hosts);
+        assert_eq!(grid.stored_items_cache[&stone].count, 1);
+        assert_eq!(drive_grid.stored_items_cache[&stone].count, 100);
+        assert_eq!(drive_grid.cell_order.len(), 2);
+
+        other.union(grid);
+        other.union(drive_grid);
+        assert_eq!(other.stored_items_cache[&stone].count, 111);
+        assert!(other.locate_cell(kept).is_some());
+    }
+
+    #[test]
+    fn test_grid_split_energy() {
+        let stone = Item::new("minecraft:stone");
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        let moved = grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        let energy = Rc::new(RefCell::new(EnergyGrid::new()));
+        energy.borrow_mut().stored = 100.0;
+        grid.energy = Some(energy.clone());
+
+        // The split-off part does not get free power, nor does it drain ours
+        let mut ids = BTreeSet::new();
+        ids.insert(moved);
+        let mut other = grid.split_off_cells(&ids);
+        assert_eq!(other.try_insert(StoredItem::new(&stone, 10)), Err(GridError::PowerFailure));
+        assert_eq!(other.insert(StoredItem::new(&stone, 10)), 10);
+        assert_eq!(energy.borrow().stored, 100.0);
+        assert_eq!(grid.try_insert(StoredItem::new(&stone, 10)), Ok(0));
+    }
+
+    #[test]
+    fn test_extraction_plan() {
+        let stone = Item::new("minecraft:stone");
+        let mut grid = Grid::default();
+        let mut ids = vec![];
+        for priority in 0..3 {
+            let mut cell = StorageCell::new(&CELL_TYPE_1K);
+            cell.config.priority = priority;
+            cell.insert(StoredItem::new(&stone, 5));
+            ids.push(grid.insert_storage_cell(cell));
+        }
+        grid.cell_by_id_mut(ids[2]).unwrap().config.access = AccessMode::Write;
+
+        // Highest readable priority first, split across cells without exceeding the request
+        let plan = grid.plan_extraction(&StoredItem::new(&stone, 7));
+        assert_eq!(plan.total, 7);
+        assert_eq!(plan.cells.iter().map(|x| x.1).collect::<Vec<_>>(), vec![5, 2]);
+        assert_eq!(plan.cells[0].0, grid.locate_cell(ids[1]).unwrap());
+
+        assert_eq!(grid.take(StoredItem::new(&stone, 7)), 7);
+        assert_eq!(grid.stored_items_cache[&stone].count, 8);
+        assert_eq!(grid.cell_by_id(ids[2]).unwrap().stored_items_count, 5);
+        assert_eq!(grid.take(StoredItem::new(&stone, 100)), 3);
+
+        // Read-only cells are skipped when inserting
+        grid.cell_by_id_mut(ids[0]).unwrap().config.access = AccessMode::Read;
+        grid.cell_by_id_mut(ids[1]).unwrap().config.access = AccessMode::Read;
+        assert_eq!(grid.insert(StoredItem::new(&stone, 10)), 0);
+        assert_eq!(grid.cell_by_id(ids[2]).unwrap().stored_items_count, 15);
+    }
+
+    #[test]
+    fn test_topology_merge_split() {
+        let stone = Item::new("minecraft:stone");
+        let mut topology = Topology::new();
+        let mut drives = vec![];
+        for (x, count) in [(0, 10), (3, 20)].iter() {
+            let mut cell = StorageCell::new(&CELL_TYPE_1K);
+            cell.insert(StoredItem::new(&stone, *count));
+            let mut host = CellHost::drive();
+            assert!(host.mount(0, cell).is_ok());
+            drives.push(topology.add_host(BlockPos::new(*x, 0, 0), host).unwrap());
+        }
+        assert_eq!(topology.networks.len(), 2);
+
+        let cable = topology.add_node(BlockPos::new(1, 0, 0), DeviceKind::Cable(CableKind::Normal)).unwrap();
+        topology.add_node(BlockPos::new(2, 0, 0), DeviceKind::Cable(CableKind::Normal)).unwrap();
+        assert!(topology.add_node(BlockPos::new(2, 0, 0), DeviceKind::Terminal).is_err());
+        assert_eq!(topology.networks.len(), 1);
+        assert_eq!(topology.network_of(drives[0]).unwrap().item_grid.stored_items_cache[&stone].count, 30);
+
+        topology.remove_node(cable).unwrap();
+        assert_eq!(topology.networks.len(), 2);
+        assert_eq!(topology.network_of(drives[0]).unwrap().item_grid.stored_items_cache[&stone].count, 10);
+        assert_eq!(topology.network_of(drives[1]).unwrap().item_grid.stored_items_cache[&stone].count, 20);
+        assert_eq!(topology.component(drives[1]).len(), 2);
+
+        let (_, host) = topology.remove_node(drives[0]).unwrap();
+        assert_eq!(host.unwrap().slots[0].as_ref().unwrap().stored_items_count, 10);
+        assert_eq!(topology.networks.len(), 1);
+    }
+
+    #[test]
+    fn test_channels() {
+        for (cable_kind, expected_online) in [(CableKind::Normal, 8), (CableKind::Dense, 9)].iter() {
+            let mut topology = Topology::new();
+            topology.add_node(BlockPos::new(0, 0, 0), DeviceKind::Controller).unwrap();
+            for x in 1..=5 {
+                topology.add_node(BlockPos::new(x, 0, 0), DeviceKind::Cable(*cable_kind)).unwrap();
+            }
+            let mut terminals = vec![];
+            for i in 0..9 {
+                let pos = BlockPos::new(1 + i / 2, if i % 2 == 0 { 1 } else { -1 }, 0);
+                terminals.push(topology.add_node(pos, DeviceKind::Terminal).unwrap());
+            }
+            let online = terminals.iter().filter(|x| topology.is_online(**x)).count();
+            assert_eq!(online, *expected_online);
+            assert_eq!(topology.channels.offline.len(), 9 - expected_online);
+        }
+    }
+
+    #[test]
+    fn test_ad_hoc_channels() {
+        let stone = Item::new("minecraft:stone");
+        let mut topology = Topology::new();
+        let mut host = CellHost::chest();
+        let mut cell = StorageCell::new(&CELL_TYPE_1K);
+        cell.insert(StoredItem::new(&stone, 10));
+        assert!(host.mount(0, cell).is_ok());
+        let chest = topology.add_host(BlockPos::new(0, 0, 0), host).unwrap();
+        let mut terminals = vec![];
+        for x in 1..=8 {
+            topology.add_node(BlockPos::new(x, 1, 0), DeviceKind::Cable(CableKind::Normal)).unwrap();
+            terminals.push(topology.add_node(BlockPos::new(x, 2, 0), DeviceKind::Terminal).unwrap());
+        }
+        topology.add_node(BlockPos::new(0, 1, 0), DeviceKind::Cable(CableKind::Normal)).unwrap();
+
+        // Nine devices without a controller, nothing works
+        assert!(!topology.is_online(chest));
+        assert!(topology.network_of(chest).unwrap().item_grid.stored_items_cache.is_empty());
+
+        topology.remove_node(terminals[7]).unwrap();
+        assert!(topology.is_online(chest));
+        assert_eq!(topology.network_of(chest).unwrap().item_grid.stored_items_cache[&stone].count, 10);
+    }
+
+    #[test]
+    fn test_energy_brownout() {
+        let stone = Item::new("minecraft:stone");
+        let mut topology = Topology::new();
+        let controller = topology.add_node(BlockPos::new(0, 0, 0), DeviceKind::Controller).unwrap();
+        let mut host = CellHost::drive();
+        assert!(host.mount(0, StorageCell::new(&CELL_TYPE_1K)).is_ok());
+        topology.add_host(BlockPos::new(1, 0, 0), host).unwrap();
+        let energy = topology.network_of_mut(controller).unwrap().enable_energy(EnergyGrid::new());
+        energy.borrow_mut().acceptors.push(10.0);
+
+        // Controller 3 + drive 0.5 + 1k cell 0.5 per tick
+        assert!(topology.tick_energy().is_empty());
+        assert_eq!(energy.borrow().stored, 6.0);
+
+        let grid = &mut topology.network_of_mut(controller).unwrap().item_grid;
+        assert_eq!(grid.try_insert(StoredItem::new(&stone, 50)), Ok(0));
+        assert_eq!(energy.borrow().stored, 1.0);
+        assert_eq!(grid.try_take(StoredItem::new(&stone, 20)), Err(GridError::PowerFailure));
+        assert_eq!(grid.take(StoredItem::new(&stone, 20)), 0);
+        assert_eq!(grid.try_take(StoredItem::new(&stone, 10)), Ok(10));
+
+        energy.borrow_mut().acceptors.clear();
+        assert_eq!(topology.tick_energy().len(), 1);
+        let grid = &mut topology.network_of_mut(controller).unwrap().item_grid;
+        assert_eq!(grid.try_insert(StoredItem::new(&stone, 1)), Err(GridError::PowerFailure));
+        assert_eq!(grid.insert(StoredItem::new(&stone, 1)), 1);
+        assert_eq!(grid.stored_items_cache[&stone].count, 40);
+
+        // Stacks that only overflow once added up
+        let huge = vec![StoredItem::new(&stone, 2_000_000_000), StoredItem::new(&stone, 2_000_000_000)];
+        assert_eq!(grid.try_insert_many(huge.clone()), Err(GridError::PowerFailure));
+        assert_eq!(grid.insert_many(huge.clone()), vec![2_000_000_000, 2_000_000_000]);
+        let mut unpowered = Grid::default();
+        unpowered.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        let left = unpowered.insert_many(huge);
+        assert_eq!(left[1], 2_000_000_000);
+        assert_eq!(unpowered.stored_items_cache[&stone].count, 2_000_000_000 - left[0]);
+    }
+
+    #[test]
+    fn test_watchers() {
+        let stone = Item::new("minecraft:stone");
+        let dirt = Item::new("minecraft:dirt");
+        let changes = Rc::new(RefCell::new(vec![]));
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        let drive = grid.add_host(CellHost::drive());
+        let (_, receiver) = grid.watch_channel(WatchFilter::key(&stone));
+        let sink = changes.clone();
+        let all = grid.watch(WatchFilter::All, WatchSink::Callback(Box::new(move |x| sink.borrow_mut().push((x.old, x.new)))));
+
+        grid.insert(StoredItem::new(&stone, 10));
+        grid.insert(StoredItem::new(&dirt, 3));
+        grid.take(StoredItem::new(&stone, 4));
+        let mut cell = StorageCell::new(&CELL_TYPE_1K);
+        cell.insert(StoredItem::new(&stone, 100));
+        assert!(grid.mount_cell(drive, 0, cell).is_ok());
+        grid.eject_cell(drive, 0);
+
+        let stone_changes: Vec<(i32, i32)> = receiver.try_iter().map(|x| (x.old, x.new)).collect();
+        assert_eq!(stone_changes, vec![(0, 10), (10, 6), (6, 106), (106, 6)]);
+        assert_eq!(changes.borrow().len(), 5);
+
+        assert!(grid.unwatch(all));
+        drop(receiver);
+        grid.insert(StoredItem::new(&stone, 1));
+        assert_eq!(changes.borrow().len(), 5);
+        assert!(grid.watchers.is_empty());
+    }
+
+    #[test]
+    fn test_level_emitter() {
+        let stone = Item::new("minecraft:stone");
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        grid.insert(StoredItem::new(&stone, 5));
+        let mut emitter = LevelEmitter::new(&mut grid, &stone, 10, EmitterMode::AboveOrEqual);
+        assert!(!emitter.is_on());
+
+        grid.insert(StoredItem::new(&stone, 4));
+        assert!(emitter.poll().is_empty());
+        grid.insert(StoredItem::new(&stone, 1));
+        grid.take(StoredItem::new(&stone, 1));
+        grid.insert(StoredItem::new(&stone, 6));
+        assert_eq!(emitter.poll(), vec![EmitterEdge::Rising, EmitterEdge::Falling, EmitterEdge::Rising]);
+        assert_eq!(emitter.amount(), 15);
+
+        assert_eq!(emitter.configure(16, EmitterMode::Below), None);
+        assert_eq!(emitter.configure(15, EmitterMode::Below), Some(EmitterEdge::Falling));
+        // Changes not polled yet count as well
+        grid.take(StoredItem::new(&stone, 10));
+        assert_eq!(emitter.configure(10, EmitterMode::Below), Some(EmitterEdge::Rising));
+        assert_eq!(emitter.amount(), 5);
+        assert!(emitter.poll().is_empty());
+        emitter.detach(&mut grid);
+        assert!(grid.watchers.is_empty());
+    }
+
+    #[test]
+    fn test_import_export_bus() {
+        let stone = Item::new("minecraft:stone");
+        let dirt = Item::new("minecraft:dirt");
+        let mut grid = Grid::default();
+        let cell_id = grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        let mut chest = Inventory::new(3);
+        assert_eq!(chest.insert(StoredItem::new(&stone, 100), false), 0);
+        assert_eq!(chest.insert(StoredItem::new(&dirt, 100), false), 36);
+
+        let mut import = ImportBus::new(BusConfig::with_filter(vec![&stone, &dirt]));
+        assert_eq!(import.tick(&mut grid, &mut chest), 1);
+        import.config.install(UpgradeCard::Acceleration).unwrap();
+        import.config.install(UpgradeCard::Acceleration).unwrap();
+        assert_eq!(import.tick(&mut grid, &mut chest), 32);
+        assert_eq!(grid.stored_items_cache.get(&stone).unwrap().count, 33);
+        // Dirt sits behind a locked filter slot
+        assert_eq!(chest.count(&dirt), 64);
+
+        // Nothing leaves the chest while the grid cannot take it
+        grid.cell_by_id_mut(cell_id).unwrap().config.access = AccessMode::Read;
+        assert_eq!(import.tick(&mut grid, &mut chest), 0);
+        assert_eq!(chest.count(&stone), 67);
+        grid.cell_by_id_mut(cell_id).unwrap().config.access = AccessMode::ReadWrite;
+
+        let mut export = ExportBus::new(BusConfig::with_filter(vec![&stone]));
+        export.config.install(UpgradeCard::Acceleration).unwrap();
+        let mut furnace = Inventory::new(1);
+        furnace.insert(StoredItem::new(&stone, 60), false);
+        assert_eq!(export.tick(&mut grid, &mut furnace), 4);
+        assert_eq!(export.tick(&mut grid, &mut furnace), 0);
+        assert_eq!(furnace.count(&stone), 64);
+        assert_eq!(grid.stored_items_cache.get(&stone).unwrap().count, 29);
+
+        // Held items go out first and stay held while the inventory is full
+        export.held.push(StoredItem::new(&stone, 10));
+        assert_eq!(export.tick(&mut grid, &mut furnace), 0);
+        assert_eq!(export.held, vec![StoredItem::new(&stone, 10)]);
+        furnace.extract(&stone, 64, false);
+        assert_eq!(export.tick(&mut grid, &mut furnace), 8);
+        assert_eq!(export.held, vec![StoredItem::new(&stone, 2)]);
+        assert_eq!(grid.stored_items_cache.get(&stone).unwrap().count, 29);
+    }
+
+    struct RandomProducer<'a> {
+        item: &'a Item,
+    }
+
+    impl<'a> Device<'a, Item> for RandomProducer<'a> {
+        fn tick(&mut self, ctx: &mut TickContext<'_, 'a, Item>) -> i32 {
+            let count = ctx.rng.below(ctx.budget as u64 + 1) as i32;
+            count - ctx.grid.insert(StoredItem::new(self.item, count))
+        }
+    }
+
+    #[test]
+    fn test_simulation() {
+        let stone = Item::new("minecraft:stone");
+        let run = |seed: u64| {
+            let mut grid = Grid::default();
+            grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+            let chest = Rc::new(RefCell::new(Inventory::new(1)));
+            let mut export = ExportBus::new(BusConfig::with_filter(vec![&stone]));
+            export.config.install(UpgradeCard::Acceleration).unwrap();
+            let mut simulation = Simulation::new(seed);
+            simulation.tick_budget = Some(10);
+            // Registered first but runs last
+            simulation.add_device(Attached::new(export, chest.clone()), 1, 8);
+            simulation.add_device(RandomProducer { item: &stone }, 0, 6);
+            simulation.run(&mut grid, 20);
+            let exported = chest.borrow().count(&stone);
+            (simulation.drain_events(), serde_json::to_string(&grid.stored_items_cache).unwrap(), exported)
+        };
+        let (events, grid, exported) = run(7);
+        assert_eq!(run(7), (events.clone(), grid, exported));
+        assert_ne!(run(8).0, events);
+        // The producer always goes first and leaves at least 4 of the 10 per tick to the bus
+        assert!(events.windows(2).all(|x| x[0].tick <= x[1].tick));
+        assert!(events.iter().all(|x| match x.kind {
+            SimEventKind::Worked(spent) => spent <= 8,
+            _ => false
+        }));
+        assert!(exported > 0);
+    }
+
+    #[test]
+    fn test_simulation_budget() {
+        let stone = Item::new("minecraft:stone");
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        let chest = Rc::new(RefCell::new(Inventory::new(2)));
+        chest.borrow_mut().insert(StoredItem::new(&stone, 100), false);
+        let mut simulation = Simulation::new(0);
+        simulation.tick_budget = Some(10);
+        let mut ids = vec![];
+        for _ in 0..3 {
+            let mut import = ImportBus::new(BusConfig::default());
+            import.config.install(UpgradeCard::Acceleration).unwrap();
+            ids.push(simulation.add_device(Attached::new(import, chest.clone()), 0, 64));
+        }
+        simulation.tick(&mut grid);
+        assert_eq!(simulation.current_tick(), 1);
+        assert_eq!(grid.stored_items_cache.get(&stone).unwrap().count, 10);
+        let kinds: Vec<SimEventKind> = simulation.events().iter().map(|x| x.kind.clone()).collect();
+        assert_eq!(kinds, vec![SimEventKind::Worked(8), SimEventKind::Worked(2), SimEventKind::Deferred]);
+
+        assert!(simulation.remove_device(ids[0]));
+        simulation.tick_budget = None;
+        simulation.tick(&mut grid);
+        assert_eq!(grid.stored_items_cache.get(&stone).unwrap().count, 26);
+    }
+
+    #[test]
+    fn test_interface_stocking() {
+        let stone = Item::new("minecraft:stone");
+        let dirt = Item::new("minecraft:dirt");
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        grid.insert(StoredItem::new(&stone, 100));
+        let interface = Rc::new(RefCell::new(Interface::new()));
+        assert!(interface.borrow_mut().configure(0, Some(StoredItem::new(&stone, 16))).is_ok());
+        assert!(interface.borrow_mut().configure(1, Some(StoredItem::new(&dirt, 8))).is_ok());
+        assert!(interface.borrow_mut().configure(2, Some(StoredItem::new(&stone, 100))).is_ok());
+        assert_eq!(interface.borrow_mut().configure(9, None), Err(None));
+        assert_eq!(interface.borrow().demand(9), None);
+        assert_eq!(interface.borrow().config()[2].as_ref().unwrap().count, 64);
+        grid.attach_interface(interface.clone());
+        let (_, changes) = grid.watch_channel(WatchFilter::key(&stone));
+
+        // Stocking from its own grid moves items around without changing the total
+        assert_eq!(Interface::stock(&interface, &mut grid, 40), 40);
+        assert_eq!(Interface::stock(&interface, &mut grid, 64), 40);
+        assert_eq!(interface.borrow().inventory.count(&stone), 80);
+        assert_eq!(grid.stored_items_cache.get(&stone).unwrap().count, 100);
+        assert!(changes.try_recv().is_err());
+
+        // A machine draining the slots is topped up from the cells, never from the interface itself
+        interface.borrow_mut().inventory.extract(&stone, 10, false);
+        grid.refresh_cache();
+        assert_eq!(changes.try_recv().unwrap().new, 90);
+        assert_eq!(Interface::stock(&interface, &mut grid, 64), 10);
+        assert_eq!(grid.stored_items_cache.get(&stone).unwrap().count, 90);
+        assert_eq!(grid.simulate_take_bypassing(&StoredItem::new(&stone, 100), interface.borrow().id), 10);
+
+        // The grid can still reach the slots as storage, after its own cells
+        assert_eq!(grid.take(StoredItem::new(&stone, 30)), 30);
+        assert_eq!(interface.borrow().inventory.count(&stone), 60);
+        assert_eq!(grid.insert(StoredItem::new(&dirt, 20)), 0);
+        assert_eq!(interface.borrow().inventory.count(&dirt), 0);
+
+        // Unconfigured contents go back, the emptied slot refills once there is stone again
+        assert!(interface.borrow_mut().configure(2, None).is_ok());
+        interface.borrow_mut().inventory.slots[3] = Some(StoredItem::new(&dirt, 5));
+        let mut simulation = Simulation::new(0);
+        simulation.add_device(interface.clone(), 0, 64);
+        simulation.run(&mut grid, 2);
+        assert_eq!(interface.borrow().inventory.count(&stone), 16);
+        assert_eq!(interface.borrow().inventory.count(&dirt), 8);
+        assert_eq!(grid.stored_items_cache.get(&stone).unwrap().count, 60);
+        assert_eq!(grid.stored_items_cache.get(&dirt).unwrap().count, 25);
+        assert!(grid.detach_grid(interface.borrow().id).is_none());
+        assert!(grid.detach_interface(interface.borrow().id).is_some());
+        assert_eq!(grid.stored_items_cache.get(&stone).unwrap().count, 44);
+    }
+
+    #[test]
+    fn test_crafting_plan() {
+        let log = Item::new("minecraft:oak_log");
+        let planks = Item::new("minecraft:oak_planks");
+        let stick = Item::new("minecraft:stick");
+        let coal = Item::new("minecraft:coal");
+        let torch = Item::new("minecraft:torch");
+        let mut book = PatternBook::new();
+        let planks_pattern = book.add(Pattern::crafting(vec![StoredItem::new(&log, 1)], vec![StoredItem::new(&planks, 4)]));
+        let stick_pattern = book.add(Pattern::crafting(vec![StoredItem::new(&planks, 2)], vec![StoredItem::new(&stick, 4)]));
+        let torch_pattern = book.add(Pattern::crafting(
+            vec![StoredItem::new(&coal, 1), StoredItem::new(&stick, 1)],
+            vec![StoredItem::new(&torch, 4)]
+        ));
+        // Loops back to planks, must not send the planner in circles
+        book.add(Pattern::crafting(vec![StoredItem::new(&planks, 4)], vec![StoredItem::new(&log, 1)]));
+
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        grid.insert(StoredItem::new(&log, 2));
+        grid.insert(StoredItem::new(&coal, 1));
+        assert_eq!(book.plan(&grid, StoredItem::new(&torch, 8)), Err(CraftingError::Missing(vec![StoredItem::new(&coal, 1)])));
+        assert_eq!(book.plan(&grid, StoredItem::new(&torch, 100)).unwrap_err(), CraftingError::Missing(vec![
+            StoredItem::new(&coal, 24),
+            StoredItem::new(&log, 2)
+        ]));
+        // Four torches a craft, the last craft would make more than an i32 holds
+        assert_eq!(book.plan(&grid, StoredItem::new(&torch, i32::MAX)), Err(CraftingError::TooLarge(&torch)));
+
+        grid.insert(StoredItem::new(&coal, 4));
+        let plan = book.plan(&grid, StoredItem::new(&torch, 8)).unwrap();
+        assert_eq!(plan.used, vec![(&log, 1), (&coal, 2)].into_iter().collect());
+        assert_eq!(plan.steps, vec![
+            CraftingStep { pattern: planks_pattern, crafts: 1 },
+            CraftingStep { pattern: stick_pattern, crafts: 1 },
+            CraftingStep { pattern: torch_pattern, crafts: 2 }
+        ]);
+        assert_eq!(plan.leftovers, vec![(&planks, 2), (&stick, 2)].into_iter().collect());
+
+        // Whatever is in storage is used before crafting
+        grid.insert(StoredItem::new(&torch, 3));
+        let plan = book.plan(&grid, StoredItem::new(&torch, 3)).unwrap();
+        assert!(plan.steps.is_empty());
+        assert_eq!(plan.used, vec![(&torch, 3)].into_iter().collect());
+    }
+
+    #[test]
+    fn test_crafting_cpu() {
+        let log = Item::new("minecraft:oak_log");
+        let planks = Item::new("minecraft:oak_planks");
+        let stick = Item::new("minecraft:stick");
+        let coal = Item::new("minecraft:coal");
+        let torch = Item::new("minecraft:torch");
+        let mut book = PatternBook::new();
+        book.add(Pattern::crafting(vec![StoredItem::new(&log, 1)], vec![StoredItem::new(&planks, 4)]));
+        book.add(Pattern::crafting(vec![StoredItem::new(&planks, 2)], vec![StoredItem::new(&stick, 4)]));
+        book.add(Pattern::crafting(
+            vec![StoredItem::new(&coal, 1), StoredItem::new(&stick, 1)],
+            vec![StoredItem::new(&torch, 4)]
+        ));
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        grid.insert(StoredItem::new(&log, 2));
+        grid.insert(StoredItem::new(&coal, 5));
+        let plan = book.plan(&grid, StoredItem::new(&torch, 8)).unwrap();
+        assert_eq!(plan.bytes(&book), 19);
+
+        let mut small = CraftingCpu::new(16, 0);
+        assert_eq!(small.submit(&book, plan.clone(), &mut grid, 0), Err(CpuError::TooLarge { required: 19, available: 16 }));
+        assert_eq!(small.submit(&PatternBook::new(), plan.clone(), &mut grid, 0), Err(CpuError::UnknownPattern(plan.steps[0].pattern)));
+        // Storage changed after planning, nothing gets reserved
+        let mut cpu = CraftingCpu::new(CRAFTING_STORAGE_1K, 1);
+        grid.take(StoredItem::new(&coal, 4));
+        assert_eq!(cpu.submit(&book, plan.clone(), &mut grid, 0), Err(CpuError::Missing(vec![StoredItem::new(&coal, 1)])));
+        assert_eq!(grid.stored_items_cache.get(&log).unwrap().count, 2);
+        // What no longer fits back stays with the CPU until storage has room
+        grid.storage_cells[0].config.access = AccessMode::Read;
+        assert_eq!(cpu.submit(&book, plan.clone(), &mut grid, 0), Err(CpuError::Missing(vec![StoredItem::new(&coal, 1)])));
+        assert_eq!(cpu.job().unwrap().state(), JobState::Cancelling);
+        assert_eq!(cpu.job().unwrap().holding.values().sum::<i32>(), 2);
+        assert_eq!(grid.stored_items_cache.get(&log).unwrap().count, 1);
+        grid.storage_cells[0].config.access = AccessMode::ReadWrite;
+        cpu.tick(&mut grid, 0, 64);
+        assert!(!cpu.is_busy());
+        assert_eq!(grid.stored_items_cache.get(&log).unwrap().count, 2);
+        grid.insert(StoredItem::new(&coal, 4));
+
+        let cpu = Rc::new(RefCell::new(cpu));
+        cpu.borrow_mut().submit(&book, plan.clone(), &mut grid, 0).unwrap();
+        assert_eq!(cpu.borrow_mut().submit(&book, plan, &mut grid, 0), Err(CpuError::Busy));
+        assert_eq!(grid.stored_items_cache.get(&log).unwrap().count, 1);
+        assert_eq!(grid.stored_items_cache.get(&coal).unwrap().count, 3);
+        let status = cpu.borrow().status(0).unwrap();
+        assert_eq!((status.state, status.crafts_total, status.estimated_completion), (JobState::Running, 4, 3));
+
+        let mut simulation = Simulation::new(0);
+        simulation.add_device(cpu.clone(), 0, 64);
+        simulation.run(&mut grid, 3);
+        let status = cpu.borrow().status(2).unwrap();
+        assert_eq!(status.state, JobState::Running);
+        assert_eq!(status.progress(), 0.5);
+        assert_eq!(status.estimated_completion, 3);
+        // Both torch crafts went out in the same tick thanks to the co-processor
+        let dispatched: Vec<(u64, SimEventKind)> = simulation.events().iter().map(|x| (x.tick, x.kind.clone())).collect();
+        assert_eq!(dispatched, vec![(0, SimEventKind::Worked(1)), (1, SimEventKind::Worked(1)), (2, SimEventKind::Worked(2))]);
+
+        simulation.tick(&mut grid);
+        assert_eq!(cpu.borrow().status(3).unwrap().state, JobState::Done);
+        assert!(!cpu.borrow().is_busy());
+        assert_eq!(grid.stored_items_cache.get(&torch).unwrap().count, 8);
+        assert_eq!(grid.stored_items_cache.get(&planks).unwrap().count, 2);
+        assert_eq!(grid.stored_items_cache.get(&stick).unwrap().count, 2);
+    }
+
+    #[test]
+    fn test_crafting_cpu_large_plan() {
+        let stone = Item::new("minecraft:stone");
+        let dirt = Item::new("minecraft:dirt");
+        let brick = Item::new("minecraft:brick");
+        let wall = Item::new("minecraft:brick_wall");
+        let mut book = PatternBook::new();
+        book.add(Pattern::crafting(vec![StoredItem::new(&stone, 1), StoredItem::new(&dirt, 1)], vec![StoredItem::new(&brick, 1)]));
+        book.add(Pattern::crafting(vec![StoredItem::new(&brick, 1)], vec![StoredItem::new(&wall, 1)]));
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::creative(vec![&stone, &dirt]));
+        let plan = book.plan(&grid, StoredItem::new(&wall, 2_000_000_000)).unwrap();
+        assert_eq!(plan.bytes(&book), i32::MAX);
+
+        let mut cpu = CraftingCpu::new(CRAFTING_STORAGE_1K, 0);
+        assert_eq!(cpu.submit(&book, plan.clone(), &mut grid, 0), Err(CpuError::TooLarge { required: i32::MAX, available: CRAFTING_STORAGE_1K }));
+        let mut cpu = CraftingCpu::new(i32::MAX, 1);
+        cpu.submit(&book, plan, &mut grid, 0).unwrap();
+        let status = cpu.status(0).unwrap();
+        assert_eq!(status.crafts_total, i32::MAX);
+        assert_eq!(status.estimated_completion, 2_000_000_000);
+    }
+
+    #[test]
+    fn test_crafting_cancel() {
+        let log = Item::new("minecraft:oak_log");
+        let planks = Item::new("minecraft:oak_planks");
+        let stick = Item::new("minecraft:stick");
+        let mut book = PatternBook::new();
+        book.add(Pattern::crafting(vec![StoredItem::new(&log, 1)], vec![StoredItem::new(&planks, 4)]));
+        book.add(Pattern::crafting(vec![StoredItem::new(&planks, 2)], vec![StoredItem::new(&stick, 4)]));
+        let mut grid = Grid::default();
+        let cell_id = grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        grid.insert(StoredItem::new(&log, 4));
+        let mut cpu = CraftingCpu::new(CRAFTING_STORAGE_1K, 0);
+        assert!(cpu.cancel(&mut grid).is_none());
+
+        // 3 planks crafts, the first one has landed and the second is in flight
+        let plan = book.plan(&grid, StoredItem::new(&stick, 20)).unwrap();
+        cpu.submit(&book, plan, &mut grid, 0).unwrap();
+        cpu.tick(&mut grid, 0, 64);
+        cpu.tick(&mut grid, 1, 64);
+        assert_eq!(grid.stored_items_cache.get(&log).unwrap().count, 1);
+
+        // Storage refuses everything, nothing is dropped
+        grid.cell_by_id_mut(cell_id).unwrap().config.access = AccessMode::Read;
+        let report = cpu.cancel(&mut grid).unwrap();
+        assert!(report.returned.is_empty());
+        assert_eq!(report.not_returned, vec![StoredItem::new(&log, 2), StoredItem::new(&planks, 4)]);
+        assert_eq!(cpu.status(2).unwrap().state, JobState::Cancelling);
+        assert!(cpu.is_busy());
+
+        grid.cell_by_id_mut(cell_id).unwrap().config.access = AccessMode::ReadWrite;
+        assert_eq!(cpu.tick(&mut grid, 2, 64), 0);
+        assert_eq!(cpu.status(2).unwrap().state, JobState::Cancelled);
+        assert!(cpu.job().unwrap().holding.is_empty());
+        assert_eq!(grid.stored_items_cache.get(&log).unwrap().count, 3);
+        assert_eq!(grid.stored_items_cache.get(&planks).unwrap().count, 4);
+        assert!(cpu.cancel(&mut grid).is_none());
+    }
+
+    #[test]
+    fn test_processing_chain() {
+        let ore = Item::new("minecraft:iron_ore");
+        let dust = Item::new("thermal:iron_dust");
+        let ingot = Item::new("minecraft:iron_ingot");
+        let nickel = Item::new("thermal:nickel_dust");
+        let run = |seed: u64| {
+            let mut book = PatternBook::new();
+            let crush = book.add(Pattern::processing(vec![StoredItem::new(&ore, 1)], vec![StoredItem::new(&dust, 2)]));
+            let smelt = book.add(Pattern::processing(vec![StoredItem::new(&dust, 1)], vec![StoredItem::new(&ingot, 1)]));
+            let pulverizer = Rc::new(RefCell::new(Machine::new(vec![
+                MachineRecipe::new(vec![StoredItem::new(&ore, 1)], vec![StoredItem::new(&dust, 2)], 4)
+                    .with_byproduct(StoredItem::new(&nickel, 1), 0.5)
+            ], 2)));
+            let furnace = Rc::new(RefCell::new(Machine::new(vec![
+                MachineRecipe::new(vec![StoredItem::new(&dust, 1)], vec![StoredItem::new(&ingot, 1)], 2)
+            ], 2)));
+            let pulverizer_provider = Rc::new(RefCell::new(PatternProvider::new(pulverizer.clone(), vec![crush])));
+            let furnace_provider = Rc::new(RefCell::new(PatternProvider::new(furnace.clone(), vec![smelt])));
+
+            let mut grid = Grid::default();
+            grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+            grid.insert(StoredItem::new(&ore, 3));
+            let mut cpu = CraftingCpu::new(CRAFTING_STORAGE_1K, 1);
+            cpu.add_provider(pulverizer_provider.clone());
+            cpu.add_provider(furnace_provider.clone());
+            let plan = book.plan(&grid, StoredItem::new(&ingot, 6)).unwrap();
+            cpu.submit(&book, plan, &mut grid, 0).unwrap();
+            // Two waves of crushing and three of smelting with one co-processor
+            assert_eq!(cpu.status(0).unwrap().estimated_completion, 2 * 4 + 3 * 2);
+
+            let cpu = Rc::new(RefCell::new(cpu));
+            let mut simulation = Simulation::new(seed);
+            simulation.add_device(cpu.clone(), 0, 64);
+            simulation.add_device(pulverizer, 1, 64);
+            simulation.add_device(furnace, 1, 64);
+            simulation.add_device(pulverizer_provider, 2, 64);
+            simulation.add_device(furnace_provider, 2, 64);
+            while cpu.borrow().is_busy() {
+                simulation.tick(&mut grid);
+                assert!(simulation.current_tick() < 100);
+            }
+            assert_eq!(grid.stored_items_cache.get(&ingot).unwrap().count, 6);
+            assert!(!grid.stored_items_cache.contains_key(&ore));
+            assert!(!grid.stored_items_cache.contains_key(&dust));
+            (simulation.current_tick(), grid.stored_items_cache.get(&nickel).map_or(0, |x| x.count))
+        };
+        // Byproducts are rolled from the seed and returned to the grid by the provider
+        let (ticks, byproducts) = run(3);
+        assert!(ticks >= 14);
+        assert_eq!(byproducts, 1);
+        assert_eq!(run(3), (ticks, byproducts));
+    }
+
+    #[test]
+    fn test_crafting_alternatives() {
+        let ore = Item::new("minecraft:raw_gold");
+        let ingot = Item::new("minecraft:gold_ingot");
+        let block = Item::new("minecraft:gold_block");
+        let nugget = Item::new("minecraft:gold_nugget");
+        let mut book = PatternBook::new();
+        book.add(Pattern::crafting(vec![StoredItem::new(&ingot, 9)], vec![StoredItem::new(&block, 1)]));
+        let from_block = book.add(Pattern::crafting(vec![StoredItem::new(&block, 1)], vec![StoredItem::new(&ingot, 9)]));
+        let from_nuggets = book.add(Pattern::crafting(vec![StoredItem::new(&nugget, 9)], vec![StoredItem::new(&ingot, 1)]));
+        book.add(Pattern::crafting(vec![StoredItem::new(&ingot, 1)], vec![StoredItem::new(&nugget, 9)]));
+
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        assert_eq!(book.plan(&grid, StoredItem::new(&ingot, 1)), Err(CraftingError::Cyclic(vec![&ingot, &block, &ingot])));
+
+        // The route with its ingredients at hand wins
+        grid.insert(StoredItem::new(&nugget, 18));
+        let plan = book.plan(&grid, StoredItem::new(&ingot, 2)).unwrap();
+        assert_eq!(plan.steps, vec![CraftingStep { pattern: from_nuggets, crafts: 2 }]);
+        assert_eq!(plan.used, vec![(&nugget, 18)].into_iter().collect());
+        assert_eq!(book.plan(&grid, StoredItem::new(&ingot, 3)), Err(CraftingError::Missing(vec![StoredItem::new(&nugget, 9)])));
+
+        // Ties go to the pattern added first, a higher priority route that fails falls back to the next one
+        grid.insert(StoredItem::new(&block, 1));
+        let smelting = book.add(Pattern::processing(vec![StoredItem::new(&ore, 1)], vec![StoredItem::new(&ingot, 1)]).with_priority(10));
+        let plan = book.plan(&grid, StoredItem::new(&ingot, 2)).unwrap();
+        assert_eq!(plan.steps, vec![CraftingStep { pattern: from_block, crafts: 1 }]);
+        assert_eq!(plan.leftovers, vec![(&ingot, 7)].into_iter().collect());
+
+        grid.insert(StoredItem::new(&ore, 2));
+        let plan = book.plan(&grid, StoredItem::new(&ingot, 2)).unwrap();
+        assert_eq!(plan.steps, vec![CraftingStep { pattern: smelting, crafts: 2 }]);
+    }
+
+    #[test]
+    fn test_crafting_tags() {
+        let log = Item::new("minecraft:oak_log");
+        let oak = Item::new("minecraft:oak_planks");
+        let birch = Item::new("minecraft:birch_planks");
+        let spruce = Item::new("minecraft:spruce_planks");
+        let stick = Item::new("minecraft:stick");
+        let planks = Tag::with_members(TagName::new("minecraft", "planks"), vec![&oak, &birch, &spruce]);
+        assert!(planks.contains(&birch));
+        let mut book = PatternBook::new();
+        let planks_pattern = book.add(Pattern::crafting(vec![StoredItem::new(&log, 1)], vec![StoredItem::new(&oak, 4)]));
+        let stick_pattern = book.add(Pattern::crafting(vec![StoredItem::new(&oak, 2)], vec![StoredItem::new(&stick, 4)]).with_tag(0, planks));
+
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        grid.insert(StoredItem::new(&oak, 2));
+        grid.insert(StoredItem::new(&birch, 6));
+        grid.insert(StoredItem::new(&spruce, 3));
+        let plan = book.plan(&grid, StoredItem::new(&stick, 12)).unwrap();
+        assert_eq!(plan.used, vec![(&birch, 6)].into_iter().collect());
+        assert_eq!(plan.substitutions, vec![Substitution { step: 0, input: 0, item: &birch }]);
+        assert_eq!(plan.pattern(&book, 0).unwrap().inputs, vec![StoredItem::new(&birch, 2)]);
+
+        let cpu = Rc::new(RefCell::new(CraftingCpu::new(CRAFTING_STORAGE_1K, 0)));
+        cpu.borrow_mut().submit(&book, plan, &mut grid, 0).unwrap();
+        let mut simulation = Simulation::new(0);
+        simulation.add_device(cpu.clone(), 0, 64);
+        simulation.run(&mut grid, 4);
+        assert_eq!(cpu.borrow().status(4).unwrap().state, JobState::Done);
+        assert_eq!(grid.stored_items_cache.get(&stick).unwrap().count, 12);
+        assert!(grid.stored_items_cache.get(&birch).is_none_or(|x| x.count == 0));
+
+        // No member covers 8 planks, the craftable one is used
+        grid.insert(StoredItem::new(&log, 2));
+        let plan = book.plan(&grid, StoredItem::new(&stick, 28)).unwrap();
+        assert!(plan.substitutions.is_empty());
+        assert_eq!(plan.used, vec![(&log, 2), (&oak, 2), (&stick, 12)].into_iter().collect());
+        assert_eq!(plan.steps, vec![
+            CraftingStep { pattern: planks_pattern, crafts: 2 },
+            CraftingStep { pattern: stick_pattern, crafts: 4 }
+        ]);
+    }
+
+    #[test]
+    fn test_tags() {
+        assert_eq!(TagName::parse("#forge:ingots/gold"), Ok(TagName::new("forge", "ingots/gold")));
+        assert_eq!(TagName::parse("planks").unwrap().to_string(), "minecraft:planks");
+        assert_eq!(TagName::parse("Forge:Ingots"), Err(TagError::InvalidName("Forge:Ingots".to_string())));
+
+        let mut items = ItemRegistry::new();
+        for id in ["minecraft:oak_log", "minecraft:birch_log", "minecraft:oak_wood", "minecraft:stone"] {
+            items.register(Item::new(id)).unwrap();
+        }
+        let mut tags = TagRegistry::new();
+        let logs = TagName::new("minecraft", "logs");
+        let oak_logs = TagName::new("minecraft", "oak_logs");
+        tags.define(oak_logs.clone(), vec![TagEntry::parse("minecraft:oak_log").unwrap(), TagEntry::parse("minecraft:oak_wood").unwrap()]);
+        tags.define(logs.clone(), vec![TagEntry::parse("#minecraft:oak_logs").unwrap(), TagEntry::Key("minecraft:birch_log".to_string())]);
+        assert_eq!(tags.member_ids(&logs).unwrap().len(), 3);
+        assert_eq!(tags.contains(&logs, "minecraft:oak_wood"), Ok(true));
+        assert_eq!(tags.contains(&logs, "minecraft:stone"), Ok(false));
+        assert_eq!(tags.tags_of("minecraft:oak_log"), vec![logs.clone(), oak_logs.clone()]);
+        assert_eq!(tags.tags_of("minecraft:birch_log"), vec![logs.clone()]);
+
+        let resolved = tags.resolve(&logs, &items).unwrap();
+        assert_eq!(resolved.name, logs);
+        assert_eq!(serde_json::to_string(&resolved.name).unwrap(), r#""minecraft:logs""#);
+        assert!(resolved.contains(items.get_by_name("minecraft:birch_log").unwrap()));
+        tags.extend(oak_logs.clone(), vec![TagEntry::Key("minecraft:stripped_oak_log".to_string())]);
+        assert_eq!(tags.resolve::<Item, _>(&logs, &items), Err(TagError::UnknownKey {
+            tag: logs.clone(),
+            key: "minecraft:stripped_oak_log".to_string()
+        }));
+        tags.define(oak_logs.clone(), vec![TagEntry::Tag(logs.clone())]);
+        assert_eq!(tags.member_ids(&logs), Err(TagError::Cyclic(vec![logs.clone(), oak_logs.clone(), logs.clone()])));
+        assert_eq!(tags.member_ids(&TagName::new("minecraft", "planks")), Err(TagError::UnknownTag(TagName::new("minecraft", "planks"))));
+        // Members of an optional nested tag are optional as well
+        let modded_logs = TagName::new("othermod", "logs");
+        let wood = TagName::new("minecraft", "wood");
+        tags.define(modded_logs.clone(), vec![TagEntry::Key("othermod:log".to_string())]);
+        tags.define(wood.clone(), vec![TagEntry::Key("minecraft:oak_wood".to_string()), TagEntry::Tag(modded_logs.clone()).optional()]);
+        assert_eq!(tags.resolve::<Item, _>(&wood, &items).unwrap().len(), 1);
+        assert!(tags.resolve::<Item, _>(&modded_logs, &items).is_err());
+
+        // Searching storage and partitioning cells by tag
+        let stone = items.get_by_name("minecraft:stone").unwrap();
+        let oak_log = items.get_by_name("minecraft:oak_log").unwrap();
+        let mut cell = StorageCell::new(&CELL_TYPE_1K);
+        cell.partition_to(&resolved);
+        assert!(cell.accepts(oak_log) && !cell.accepts(stone));
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        grid.insert(StoredItem::new(oak_log, 5));
+        grid.insert(StoredItem::new(stone, 7));
+        assert_eq!(grid.search_tag(&resolved), vec![StoredItem::new(oak_log, 5)]);
+    }
+
+    #[test]
+    fn test_data_pack_tags() {
+        let root = std::env::temp_dir().join(format!("applied-rs-data-pack-{}", std::process::id()));
+        let write = |path: &str, json: &str| {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, json).unwrap();
+        };
+        write("base/data/minecraft/tags/items/logs.json", r##"{"values": ["#minecraft:oak_logs", "minecraft:birch_log"]}"##);
+        write("base/data/minecraft/tags/items/oak_logs.json", r##"{"values": ["minecraft:oak_log", {"id": "minecraft:stripped_oak_log", "required": false}]}"##);
+        write("base/data/minecraft/tags/items/broken.json", r##"{"values": ["#minecraft:missing", "minecraft:ston", "#minecraft:loop_a"]}"##);
+        write("base/data/minecraft/tags/items/loop_a.json", r##"{"values": ["#minecraft:loop_b"]}"##);
+        write("base/data/minecraft/tags/items/loop_b.json", r##"{"values": ["#minecraft:loop_a"]}"##);
+        write("base/data/forge/tags/items/ingots/gold.json", r##"{"values": ["minecraft:gold_ingot", {"id": "#forge:ingots/electrum", "required": false}]}"##);
+        write("base/data/minecraft/tags/fluids/water.json", r##"{"values": ["minecraft:water", "minecraft:flowing_water"]}"##);
+        write("base/data/minecraft/tags/items/readme.txt", "not a tag");
+        write("modpack/data/minecraft/tags/items/logs.json", r##"{"replace": true, "values": ["minecraft:birch_log"]}"##);
+        write("modpack/data/minecraft/tags/items/oak_logs.json", r##"{"values": ["minecraft:dark_oak_log"]}"##);
+        write("broken/data/minecraft/tags/items/logs.json", r##"{"values": "minecraft:oak_log"}"##);
+
+        let mut items = ItemRegistry::new();
+        for id in ["minecraft:oak_log", "minecraft:birch_log", "minecraft:dark_oak_log", "minecraft:gold_ingot", "minecraft:stone"] {
+            items.register(Item::new(id)).unwrap();
+        }
+        let fluids: HashMap<String, Fluid> = ["minecraft:water", "minecraft:flowing_water"].iter().map(|x| (x.to_string(), Fluid::new(x))).collect();
+        let mut tags = DataPackTags::new();
+        assert_eq!(tags.load(&root.join("base")).unwrap(), 7);
+        let logs = TagName::new("minecraft", "logs");
+        let ids = |tag: &Tag<Item>| tag.members().map(|x| x.id.clone()).collect::<Vec<String>>();
+        assert_eq!(ids(&tags.items.resolve(&logs, &items).unwrap()), vec!["minecraft:birch_log", "minecraft:oak_log"]);
+        assert_eq!(ids(&tags.items.resolve(&TagName::parse("forge:ingots/gold").unwrap(), &items).unwrap()), vec!["minecraft:gold_ingot"]);
+        assert_eq!(tags.fluids.resolve(&TagName::new("minecraft", "water"), &fluids).unwrap().len(), 2);
+        assert!(tags.fluids.check(&fluids).is_ok());
+        let broken = TagName::new("minecraft", "broken");
+        let report = tags.items.check(&items);
+        assert_eq!(report.unresolved, vec![(broken.clone(), "#minecraft:missing".to_string()), (broken, "minecraft:ston".to_string())]);
+        assert_eq!(report.cyclic, vec![vec![TagName::new("minecraft", "loop_a"), TagName::new("minecraft", "loop_b"), TagName::new("minecraft", "loop_a")]]);
+
+        // A later pack replaces or adds to what earlier ones defined
+        assert_eq!(tags.load(&root.join("modpack")).unwrap(), 2);
+        assert_eq!(ids(&tags.items.resolve(&logs, &items).unwrap()), vec!["minecraft:birch_log"]);
+        assert!(tags.items.contains(&TagName::new("minecraft", "oak_logs"), "minecraft:dark_oak_log").unwrap());
+        assert!(matches!(DataPackTags::from_dir(&root.join("broken")), Err(DataPackError::Json { .. })));
+        assert!(matches!(DataPackTags::from_dir(Path::new("/nonexistent")), Err(DataPackError::Io { .. })));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_item_registry() {
+        let mut registry = ItemRegistry::new();
+        let stone = registry.register(Item::new("minecraft:stone")).unwrap();
+        let mut red_wool = Item::new("minecraft:wool");
+        red_wool.damage = 14;
+        let wool = registry.register(Item::new("minecraft:wool")).unwrap();
+        let red = registry.register(red_wool.clone()).unwrap();
+        assert_eq!((stone.value(), wool.value(), red.value()), (0, 1, 2));
+        assert_eq!(registry.register(red_wool), Err(RegistryError::Duplicate { name: "minecraft:wool:14".to_string(), existing: red }));
+        assert_eq!(registry.len(), 3);
+        assert_eq!(registry.get_by_name("minecraft:wool:14").unwrap().damage, 14);
+        assert_eq!(registry.id_of("minecraft:wool"), Some(wool));
+        assert_eq!(registry.get(stone).unwrap().id, "minecraft:stone");
+        assert!(registry.get_by_name("minecraft:dirt").is_none());
+
+        // Removed ids are not handed out again
+        assert_eq!(registry.remove(wool).unwrap().id, "minecraft:wool");
+        assert!(registry.remove(wool).is_none() && !registry.contains("minecraft:wool"));
+        let dirt = registry.register(Item::new("minecraft:dirt")).unwrap();
+        assert_eq!(dirt.value(), 3);
+        let order: Vec<u32> = registry.iter().map(|(id, _)| id.value()).collect();
+        assert_eq!(order, vec![0, 2, 3]);
+
+        let mut grid = Grid::default();
+        grid.insert_storage_cell(StorageCell::new(&CELL_TYPE_1K));
+        grid.insert(StoredItem::new(registry.get(stone).unwrap(), 10));
+        assert_eq!(grid.stored_items_cache.get(registry.get_by_name("minecraft:stone").unwrap()).unwrap().count, 10);
+
+        // Names round-trip, so keys that would share one are rejected
+        let mut damaged = Item::new("mod:gear");
+        damaged.damage = 5;
+        let damaged = registry.register(damaged).unwrap();
+        assert_eq!(registry.id_of_key(&("mod:gear".to_string(), 5)), Some(damaged));
+        assert_eq!(registry.get_by_name(&registry.get(damaged).unwrap().registry_name()).map(|x| x.damage), Some(5));
+        assert_eq!(registry.register(Item::new("mod:gear:5")), Err(RegistryError::InvalidName("mod:gear:5".to_string())));
+        let mut bare = Item::new("stone");
+        bare.damage = 3;
+        assert_eq!(registry.register(bare), Err(RegistryError::InvalidName("stone:3".to_string())));
+        let bare = registry.register(Item::new("stone")).unwrap();
+        assert_eq!(registry.id_of(&registry.get(bare).unwrap().registry_name()), Some(bare));
+    }
+
+    #[test]
+    fn test_key_registry() {
+        let mut fluids = FluidRegistry::new();
+        let lava = fluids.register(Fluid::new("minecraft:lava").with_properties(FluidProperties {
+            temperature: 1300,
+            viscosity: 6000,
+            density: 3000,
+            gaseous: false,
+            bucket: Some("minecraft:lava_bucket".to_string())
+        })).unwrap();
+        fluids.register(Fluid::new("minecraft:water")).unwrap();
+        assert!(matches!(fluids.register(Fluid::new("minecraft:lava")), Err(RegistryError::Duplicate { existing, .. }) if existing == lava));
+        assert_eq!(fluids.get(lava).unwrap().properties.bucket.as_deref(), Some("minecraft:lava_bucket"));
+        assert_eq!(fluids.get_by_name("minecraft:water").unwrap().properties.temperature, 300);
+        let steam: Fluid = serde_json::from_str(r#"{"id": "mekanism:steam", "tag": {}, "properties": {"gaseous": true, "density": -1000}}"#).unwrap();
+        assert!(steam.properties.gaseous && steam.properties.viscosity == 1000);
+
+        // Items and fluids may share a name
+        let mut keys = KeyRegistry::new();
+        keys.fluids = fluids;
+        let water_item = keys.items.register(Item::new("minecraft:water")).unwrap();
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys.id_of(StoredItemTypes::Item, "minecraft:water"), Some(KeyId::Item(water_item)));
+        let water = keys.id_of(StoredItemTypes::Fluid, "minecraft:water").unwrap();
+        assert_eq!(water.stored_type(), StoredItemTypes::Fluid);
+        assert!(matches!(keys.get(water), Some(Key::Fluid(x)) if x.id == "minecraft:water"));
+        assert!(keys.id_of(StoredItemTypes::Item, "minecraft:lava").is_none());
+
+        let mut topology = Topology::new();
+        topology.keys = Some(&keys);
+        let controller = topology.add_node(BlockPos::new(0, 0, 0), DeviceKind::Controller).unwrap();
+        let network = topology.network_of(controller).unwrap();
+        assert!(matches!(network.resolve(KeyId::Fluid(lava)), Some(Key::Fluid(x)) if x.properties.temperature == 1300));
+        assert!(matches!(network.resolve_name(StoredItemTypes::Item, "minecraft:water"), Some(Key::Item(_))));
+        assert!(network.resolve_name(StoredItemTypes::Item, "minecraft:stone").is_none());
+    }
+
+    #[test]
+    fn test_registry_import() {
+        let json = r##"{
+            "items": [
+                {"id": "minecraft:ender_pearl", "max_stack_size": 16, "display_name": "Ender Pearl", "tags": ["forge:ender_pearls"]},
+                {"id": "minecraft:diamond_sword", "max_stack_size": 1, "max_damage": 1561},
+                {"id": "minecraft:ender_pearl", "max_stack_size": 64},
+                {"id": "minecraft:shears", "max_damage": 238},
+                {"id": "stick"},
+                {"damage": 3},
+                {"id": "#minecraft:cobblestone"}
+            ],
+            "fluids": [
+                {"id": "minecraft:lava", "temperature": 1300, "viscosity": 6000, "bucket": "minecraft:lava_bucket", "tags": ["minecraft:lava"]},
+                {"id": "minecraft:honey", "viscosity": 0}
+            ]
+        }"##;
+        let csv = "kind,id,damage,max_stack_size,display_name,tags,gaseous\n\
+            item,minecraft:wool,14,64,\"Wool, \"\"Red\"\"\",minecraft:wool;forge:wool,\n\
+            item,minecraft:wool,0,64,White Wool,minecraft:wool,\n\
+            \n\
+            item,minecraft:wool,14,64,Red Wool,,\n\
+            fluid,mekanism:steam,,,,,true\n\
+            item,minecraft:stone,lots,64,Stone,,\n\
+            block,minecraft:stone,0,64,Stone,,\n\
+            item,minecraft:dirt\n";
+
+        let mut keys = KeyRegistry::new();
+        let mut tags = DataPackTags::new();
+        let mut importer = Importer::new(&mut keys, &mut tags);
+        importer.import_json(json).unwrap();
+        let root = std::env::temp_dir().join(format!("applied-rs-import-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("dump.csv"), csv).unwrap();
+        importer.import_file(&root.join("dump.csv")).unwrap();
+        assert!(matches!(importer.import_file(&root.join("dump.txt")), Err(ImportError::Io { .. })));
+        fs::write(root.join("dump.txt"), "").unwrap();
+        assert!(matches!(importer.import_file(&root.join("dump.txt")), Err(ImportError::UnknownFormat(_))));
+        assert!(matches!(importer.import_csv("id\n\"minecraft:stone\n"), Err(ImportError::Csv { line: 2, .. })));
+        assert!(matches!(importer.import_json("{"), Err(ImportError::Json(_))));
+        fs::remove_dir_all(&root).unwrap();
+
+        let report = importer.report.clone();
+        assert_eq!((report.items, report.fluids), (4, 2));
+        assert_eq!(report.duplicates, vec![("minecraft:ender_pearl".to_string(), 1), ("minecraft:wool:14".to_string(), 1)].into_iter().collect());
+        let issue = |record: ImportRecord, reason: &str| ImportIssue { record, reason: reason.to_string() };
+        assert_eq!(report.invalid, vec![
+            issue(ImportRecord::Item(4), "minecraft:shears: items that take damage do not stack"),
+            issue(ImportRecord::Item(5), "stick: not a namespaced id"),
+            issue(ImportRecord::Item(6), "missing field `id`"),
+            issue(ImportRecord::Item(7), "#minecraft:cobblestone: not a namespaced id"),
+            issue(ImportRecord::Fluid(2), "minecraft:honey: viscosity must be positive"),
+            issue(ImportRecord::Line(7), "bad value for damage"),
+            issue(ImportRecord::Line(8), "unknown kind block"),
+            issue(ImportRecord::Line(9), "expected 7 columns, found 2")
+        ]);
+        assert!(!report.is_clean());
+
+        let pearl = keys.items.get_by_name("minecraft:ender_pearl").unwrap();
+        assert_eq!((pearl.max_stack_size, pearl.display_name.as_deref()), (16, Some("Ender Pearl")));
+        assert_eq!(keys.items.get_by_name("minecraft:wool:14").unwrap().display_name.as_deref(), Some("Wool, \"Red\""));
+        assert_eq!(keys.items.get_by_name("minecraft:diamond_sword").unwrap().max_damage, 1561);
+        assert!(keys.fluids.get_by_name("mekanism:steam").unwrap().properties.gaseous);
+        assert_eq!(keys.fluids.get_by_name("minecraft:lava").unwrap().properties.temperature, 1300);
+        assert_eq!(tags.items.member_ids(&TagName::new("minecraft", "wool")).unwrap().len(), 2);
+        assert!(tags.items.contains(&TagName::new("forge", "ender_pearls"), "minecraft:ender_pearl").unwrap());
+        assert!(tags.fluids.check(&keys.fluids).is_ok());
+
+        // Imported metadata drives slot sizes
+        let mut inventory = Inventory::new(1);
+        assert_eq!(inventory.insert(StoredItem::new(pearl, 20), false), 4);
+    }
 }
 
 fn main() {
     let mut registry = ItemRegistry::new();
-    registry.register(Item::new("minecraft:stone"));
+    registry.register(Item::new("minecraft:stone")).unwrap();
     let mut cell = StorageCell::new(&CELL_TYPE_64K);
     let mut items: Vec<Item> = vec![];
     for i in 0..63 {
@@ -92,8 +1288,8 @@ fn main() {
     println!("{:?}", cell.insert_many(stored_items.iter()));
     let duration = start.elapsed();
     println!("Time elapsed in expensive_function() is: {:?}", duration);
-    let item = Item::new("minecraft:stone");
-    let result = cell.insert(StoredItem::new(&item, 8192));
+    let item = registry.get_by_name("minecraft:stone").unwrap();
+    let result = cell.insert(StoredItem::new(item, 8192));
     println!("Insertion transactions: {:?}", result);
 }
 
@@ -108,4 +1304,19 @@ pub mod grid;
 pub mod storage;
 pub mod cache;
 pub mod log;
-pub mod fluid;
\ No newline at end of file
+pub mod fluid;
+pub mod drive;
+pub mod topology;
+pub mod channel;
+pub mod energy;
+pub mod watch;
+pub mod emitter;
+pub mod inventory;
+pub mod bus;
+pub mod simulation;
+pub mod interface;
+pub mod crafting;
+pub mod cpu;
+pub mod machine;
+pub mod datapack;
+pub mod import;
\ No newline at end of file
@@ -1,21 +1,305 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use crate::fluid::Fluid;
 use crate::item::Item;
+use crate::storage::StoredItemTypes;
+use serde::{Serialize, Serializer};
 
-pub struct ItemRegistry {
-    pub items: HashMap<String, Item>,
+/// Compact handle of a registered key, never reused within a registry.
+/// Typed by what the registry holds, so an item id cannot be used to look up a fluid.
+pub struct RegistryId<T>(u32, PhantomData<fn() -> T>);
+
+pub type ItemId = RegistryId<Item>;
+pub type FluidId = RegistryId<Fluid>;
+
+impl<T> RegistryId<T> {
+    fn new(value: u32) -> Self {
+        RegistryId(value, PhantomData)
+    }
+
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+}
+
+// Written out so they do not require anything of `T`
+impl<T> Copy for RegistryId<T> {}
+
+impl<T> Clone for RegistryId<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> fmt::Debug for RegistryId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RegistryId").field(&self.0).finish()
+    }
+}
+
+impl<T> PartialEq for RegistryId<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T> Eq for RegistryId<T> {}
+
+impl<T> PartialOrd for RegistryId<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for RegistryId<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+impl<T> Hash for RegistryId<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl<T> Serialize for RegistryId<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct("RegistryId", &self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum RegistryError<T> {
+    /// A key with the same name is registered already
+    Duplicate { name: String, existing: RegistryId<T> },
+    /// The key's name does not parse back to the same key
+    InvalidName(String),
+    /// Every numeric id has been issued
+    Full
+}
+
+impl<T> fmt::Display for RegistryError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Duplicate { name, existing } => write!(f, "Cannot register duplicate key {} (already #{})", name, existing.0),
+            RegistryError::InvalidName(name) => write!(f, "Cannot register key {}: the name does not parse back to it", name),
+            RegistryError::Full => write!(f, "No numeric ids left")
+        }
+    }
+}
+
+/// `id` for damage 0, `id:damage` otherwise
+pub fn item_name(id: &str, damage: i32) -> String {
+    if damage == 0 {
+        return id.to_string();
+    }
+    format!("{}:{}", id, damage)
+}
+
+/// Splits an `item_name` back into id and damage, `minecraft:wool:3` has damage 3
+pub fn parse_item_name(name: &str) -> (&str, i32) {
+    if let Some((id, damage)) = name.rsplit_once(':') {
+        if id.contains(':') {
+            if let Ok(damage) = damage.parse() {
+                return (id, damage);
+            }
+        }
+    }
+    (name, 0)
+}
+
+/// Anything a `Registry` can own
+pub trait RegistryKey {
+    /// Unique within a registry
+    type Name: Clone + Eq + Hash + fmt::Debug;
+
+    fn name_key(&self) -> Self::Name;
+    /// Reads a `registry_name` back
+    fn parse_name(name: &str) -> Self::Name;
+    /// Name as written in tags and dumps
+    fn registry_name(&self) -> String;
+}
+
+impl RegistryKey for Item {
+    /// Id and damage, the text form is ambiguous for ids ending in `:<number>`
+    type Name = (String, i32);
+
+    fn name_key(&self) -> Self::Name {
+        (self.id.clone(), self.damage)
+    }
+
+    fn parse_name(name: &str) -> Self::Name {
+        let (id, damage) = parse_item_name(name);
+        (id.to_string(), damage)
+    }
+
+    fn registry_name(&self) -> String {
+        item_name(&self.id, self.damage)
+    }
+}
+
+impl RegistryKey for Fluid {
+    type Name = String;
+
+    fn name_key(&self) -> Self::Name {
+        self.id.clone()
+    }
+
+    fn parse_name(name: &str) -> Self::Name {
+        name.to_string()
+    }
+
+    fn registry_name(&self) -> String {
+        self.id.clone()
+    }
+}
+
+/// Owns the definition of every known key of one type. Grids borrow their keys from here,
+/// so a key cannot be removed while a grid still refers to it.
+#[derive(Debug)]
+pub struct Registry<T: RegistryKey> {
+    /// Indexed by `RegistryId`, `None` once removed
+    entries: Vec<Option<T>>,
+    ids: HashMap<T::Name, RegistryId<T>>,
+}
+
+pub type ItemRegistry = Registry<Item>;
+pub type FluidRegistry = Registry<Fluid>;
+
+impl<T: RegistryKey> Default for Registry<T> {
+    fn default() -> Self {
+        Registry {
+            entries: vec![],
+            ids: HashMap::new()
+        }
+    }
+}
+
+impl<T: RegistryKey> Registry<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, key: T) -> Result<RegistryId<T>, RegistryError<T>> {
+        let name = key.name_key();
+        if T::parse_name(&key.registry_name()) != name {
+            return Err(RegistryError::InvalidName(key.registry_name()));
+        }
+        if let Some(existing) = self.ids.get(&name) {
+            return Err(RegistryError::Duplicate { name: key.registry_name(), existing: *existing });
+        }
+        let id = u32::try_from(self.entries.len()).map(RegistryId::new).map_err(|_| RegistryError::Full)?;
+        self.entries.push(Some(key));
+        self.ids.insert(name, id);
+        Ok(id)
+    }
+
+    /// Removes the key, its numeric id stays taken
+    pub fn remove(&mut self, id: RegistryId<T>) -> Option<T> {
+        let key = self.entries.get_mut(id.0 as usize)?.take()?;
+        self.ids.remove(&key.name_key());
+        Some(key)
+    }
+
+    pub fn get(&self, id: RegistryId<T>) -> Option<&T> {
+        self.entries.get(id.0 as usize)?.as_ref()
+    }
+
+    /// By `RegistryKey::registry_name`, e.g. `minecraft:stone` or `minecraft:wool:3` for items
+    pub fn get_by_name(&self, name: &str) -> Option<&T> {
+        self.id_of(name).and_then(|x| self.get(x))
+    }
+
+    pub fn id_of(&self, name: &str) -> Option<RegistryId<T>> {
+        self.id_of_key(&T::parse_name(name))
+    }
+
+    /// By `RegistryKey::name_key`, tells apart keys whose text names look the same
+    pub fn id_of_key(&self, name: &T::Name) -> Option<RegistryId<T>> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.id_of(name).is_some()
+    }
+
+    /// In registration order
+    pub fn iter(&self) -> impl Iterator<Item = (RegistryId<T>, &T)> {
+        self.entries.iter().enumerate().filter_map(|(i, x)| x.as_ref().map(|x| (RegistryId::new(i as u32), x)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+/// Id of a key of any stored type
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum KeyId {
+    Item(ItemId),
+    Fluid(FluidId)
+}
+
+impl KeyId {
+    pub fn stored_type(&self) -> StoredItemTypes {
+        match self {
+            KeyId::Item(_) => StoredItemTypes::Item,
+            KeyId::Fluid(_) => StoredItemTypes::Fluid
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Key<'a> {
+    Item(&'a Item),
+    Fluid(&'a Fluid)
 }
 
-impl ItemRegistry {
+/// Every registry of a game, one per `StoredItemTypes`
+#[derive(Debug, Default)]
+pub struct KeyRegistry {
+    pub items: ItemRegistry,
+    pub fluids: FluidRegistry,
+}
+
+impl KeyRegistry {
     pub fn new() -> Self {
-        ItemRegistry {
-            items: HashMap::new()
+        Self::default()
+    }
+
+    pub fn get(&self, id: KeyId) -> Option<Key<'_>> {
+        match id {
+            KeyId::Item(id) => self.items.get(id).map(Key::Item),
+            KeyId::Fluid(id) => self.fluids.get(id).map(Key::Fluid)
         }
     }
 
-    pub fn register(&mut self, item: Item) {
-        if self.items.contains_key(&item.id) {
-            panic!("Cannot register duplicate key {}", item.id);
+    /// Items and fluids may share a name, so the type has to be given
+    pub fn id_of(&self, stored_type: StoredItemTypes, name: &str) -> Option<KeyId> {
+        match stored_type {
+            StoredItemTypes::Item => self.items.id_of(name).map(KeyId::Item),
+            StoredItemTypes::Fluid => self.fluids.id_of(name).map(KeyId::Fluid)
         }
-        self.items.insert(item.id.to_string(), item);
     }
-}
\ No newline at end of file
+
+    pub fn get_by_name(&self, stored_type: StoredItemTypes, name: &str) -> Option<Key<'_>> {
+        self.id_of(stored_type, name).and_then(|x| self.get(x))
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len() + self.fluids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,282 @@
+use crate::bus::{ExportBus, ImportBus};
+use crate::cpu::CraftingCpu;
+use crate::emitter::{EmitterEdge, LevelEmitter};
+use crate::grid::Grid;
+use crate::interface::Interface;
+use crate::inventory::Inventory;
+use crate::machine::{Machine, PatternProvider};
+use crate::storage::StoredItemType;
+use crate::topology::DeviceKind;
+use std::cmp::min;
+use std::rc::Rc;
+use std::cell::RefCell;
+use serde::Serialize;
+
+/// SplitMix64, small and fully reproducible from its seed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SimRng {
+    state: u64,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        SimRng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..bound`, `bound` must not be 0
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    /// True with the given probability
+    pub fn chance(&mut self, probability: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct DeviceId(usize);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum SimEventKind {
+    /// Units of work spent by the device
+    Worked(i32),
+    /// Skipped because the tick budget ran out before the device's turn
+    Deferred,
+    Signal(EmitterEdge)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SimEvent {
+    pub tick: u64,
+    pub device: DeviceId,
+    pub kind: SimEventKind,
+}
+
+/// What a device gets to see during its turn
+pub struct TickContext<'g, 'a, T: StoredItemType> {
+    pub tick: u64,
+    pub device: DeviceId,
+    /// Most work the device may do this turn
+    pub budget: i32,
+    pub grid: &'g mut Grid<'a, T>,
+    pub rng: &'g mut SimRng,
+    events: &'g mut Vec<SimEvent>,
+}
+
+impl<'g, 'a, T: StoredItemType> TickContext<'g, 'a, T> {
+    pub fn emit(&mut self, kind: SimEventKind) {
+        self.events.push(SimEvent {
+            tick: self.tick,
+            device: self.device,
+            kind
+        });
+    }
+}
+
+/// Anything the simulation runs once per tick
+pub trait Device<'a, T: StoredItemType> {
+    /// Does up to `ctx.budget` units of work, usually items moved, and returns the units spent
+    fn tick(&mut self, ctx: &mut TickContext<'_, 'a, T>) -> i32;
+
+    /// AE/t drawn while the device is part of the simulation
+    fn idle_drain(&self) -> f64 {
+        0.0
+    }
+}
+
+struct Registered<'a, T: StoredItemType> {
+    id: DeviceId,
+    priority: i32,
+    budget: i32,
+    device: Box<dyn Device<'a, T> + 'a>,
+}
+
+/// Advances a grid tick by tick. Devices run in ascending priority, ties in registration order,
+/// so two runs with the same seed and the same setup produce the same events and the same grid.
+pub struct Simulation<'a, T: StoredItemType> {
+    /// Work all devices together may do in one tick, `None` for unlimited
+    pub tick_budget: Option<i32>,
+    seed: u64,
+    tick: u64,
+    rng: SimRng,
+    devices: Vec<Registered<'a, T>>,
+    events: Vec<SimEvent>,
+    next_device_id: usize,
+}
+
+impl<'a, T: StoredItemType> Simulation<'a, T> {
+    pub fn new(seed: u64) -> Self {
+        Simulation {
+            tick_budget: None,
+            seed,
+            tick: 0,
+            rng: SimRng::new(seed),
+            devices: vec![],
+            events: vec![],
+            next_device_id: 0
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Ticks completed so far
+    pub fn current_tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn events(&self) -> &[SimEvent] {
+        &self.events
+    }
+
+    pub fn drain_events(&mut self) -> Vec<SimEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Registers a device that may do at most `budget` units of work per tick
+    pub fn add_device<D: Device<'a, T> + 'a>(&mut self, device: D, priority: i32, budget: i32) -> DeviceId {
+        let id = DeviceId(self.next_device_id);
+        self.next_device_id += 1;
+        let index = self.devices.partition_point(|x| x.priority <= priority);
+        self.devices.insert(index, Registered {
+            id,
+            priority,
+            budget,
+            device: Box::new(device)
+        });
+        id
+    }
+
+    pub fn remove_device(&mut self, id: DeviceId) -> bool {
+        let len = self.devices.len();
+        self.devices.retain(|x| x.id != id);
+        self.devices.len() != len
+    }
+
+    /// AE/t drawn by all registered devices
+    pub fn idle_drain(&self) -> f64 {
+        self.devices.iter().map(|x| x.device.idle_drain()).sum()
+    }
+
+    /// Pays the idle drain, then gives every device its turn
+    pub fn tick(&mut self, grid: &mut Grid<'a, T>) {
+        if let Some(energy) = &grid.energy {
+            energy.borrow_mut().tick(grid.idle_drain() + self.idle_drain());
+        }
+        let mut remaining = self.tick_budget;
+        for entry in self.devices.iter_mut() {
+            let budget = min(entry.budget, remaining.unwrap_or(i32::MAX));
+            let mut ctx = TickContext {
+                tick: self.tick,
+                device: entry.id,
+                budget,
+                grid: &mut *grid,
+                rng: &mut self.rng,
+                events: &mut self.events
+            };
+            if budget <= 0 {
+                ctx.emit(SimEventKind::Deferred);
+                continue;
+            }
+            let spent = entry.device.tick(&mut ctx).clamp(0, budget);
+            if spent > 0 {
+                ctx.emit(SimEventKind::Worked(spent));
+            }
+            if let Some(remaining) = remaining.as_mut() {
+                *remaining -= spent;
+            }
+        }
+        self.tick += 1;
+    }
+
+    pub fn run(&mut self, grid: &mut Grid<'a, T>, ticks: u64) {
+        for _ in 0..ticks {
+            self.tick(grid);
+        }
+    }
+}
+
+/// Device working on an adjacent inventory
+pub struct Attached<'a, D, T: StoredItemType> {
+    pub device: D,
+    pub inventory: Rc<RefCell<Inventory<'a, T>>>,
+}
+
+impl<'a, D, T: StoredItemType> Attached<'a, D, T> {
+    pub fn new(device: D, inventory: Rc<RefCell<Inventory<'a, T>>>) -> Self {
+        Attached { device, inventory }
+    }
+}
+
+impl<'a, T: StoredItemType> Device<'a, T> for Attached<'a, ImportBus<'a, T>, T> {
+    fn tick(&mut self, ctx: &mut TickContext<'_, 'a, T>) -> i32 {
+        let limit = min(ctx.budget, self.device.config.items_per_operation());
+        self.device.transfer(ctx.grid, &mut self.inventory.borrow_mut(), limit)
+    }
+
+    fn idle_drain(&self) -> f64 {
+        DeviceKind::ImportBus.idle_drain()
+    }
+}
+
+impl<'a, T: StoredItemType> Device<'a, T> for Attached<'a, ExportBus<'a, T>, T> {
+    fn tick(&mut self, ctx: &mut TickContext<'_, 'a, T>) -> i32 {
+        let limit = min(ctx.budget, self.device.config.items_per_operation());
+        self.device.transfer(ctx.grid, &mut self.inventory.borrow_mut(), limit)
+    }
+
+    fn idle_drain(&self) -> f64 {
+        DeviceKind::ExportBus.idle_drain()
+    }
+}
+
+impl<'a, T: StoredItemType> Device<'a, T> for Rc<RefCell<Interface<'a, T>>> {
+    fn tick(&mut self, ctx: &mut TickContext<'_, 'a, T>) -> i32 {
+        Interface::stock(self, ctx.grid, ctx.budget)
+    }
+
+    fn idle_drain(&self) -> f64 {
+        DeviceKind::Interface.idle_drain()
+    }
+}
+
+impl<'a, T: StoredItemType> Device<'a, T> for Rc<RefCell<CraftingCpu<'a, T>>> {
+    /// Work is crafts dispatched
+    fn tick(&mut self, ctx: &mut TickContext<'_, 'a, T>) -> i32 {
+        self.borrow_mut().tick(ctx.grid, ctx.tick, ctx.budget)
+    }
+}
+
+impl<'a, T: StoredItemType> Device<'a, T> for Rc<RefCell<Machine<'a, T>>> {
+    /// Work is recipes started
+    fn tick(&mut self, ctx: &mut TickContext<'_, 'a, T>) -> i32 {
+        self.borrow_mut().tick(ctx.tick, ctx.rng) as i32
+    }
+}
+
+impl<'a, T: StoredItemType> Device<'a, T> for Rc<RefCell<PatternProvider<'a, T>>> {
+    /// Work is surplus outputs returned to the grid
+    fn tick(&mut self, ctx: &mut TickContext<'_, 'a, T>) -> i32 {
+        self.borrow_mut().return_surplus(ctx.grid, ctx.budget)
+    }
+}
+
+impl<'a, T: StoredItemType> Device<'a, T> for LevelEmitter<T> {
+    /// Reports every flip since the last tick, costs no work
+    fn tick(&mut self, ctx: &mut TickContext<'_, 'a, T>) -> i32 {
+        for edge in self.poll() {
+            ctx.emit(SimEventKind::Signal(edge));
+        }
+        0
+    }
+}
@@ -1,19 +1,26 @@
-use crate::item::{Item};
-use std::collections::BTreeMap;
-use std::cmp::{max, min, Ordering};
+use std::collections::{BTreeMap, BTreeSet};
+use std::cmp::{min, Ordering};
 use std::slice::Iter;
 use std::ops::Add;
-use std::convert::TryInto;
 use crate::log::Transactions;
 use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 
+static NEXT_CELL_ID: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
 pub enum StoredItemTypes {
     Item,
     Fluid
 }
 
-pub trait StoredItemType: Sized + Sync + Send + PartialEq + PartialOrd + Ord + Serialize {
+pub trait StoredItemType: Sized + Clone + Sync + Send + PartialEq + PartialOrd + Ord + Serialize {
     fn stored_type() -> StoredItemTypes;
+
+    /// Most a single inventory slot holds
+    fn stack_limit(&self) -> i32 {
+        64
+    }
 }
 
 /// Capacity, max item types
@@ -25,6 +32,10 @@ impl StorageCellType {
         StorageCellType(capacity, 63)
     }
 
+    pub fn get_capacity(&self) -> i32 {
+        self.0
+    }
+
     pub fn get_bytes_per_type(&self) -> i32 {
         self.0 / 128
     }
@@ -34,6 +45,10 @@ pub const CELL_TYPE_1K: StorageCellType = StorageCellType(1024, 63);
 pub const CELL_TYPE_4K: StorageCellType = StorageCellType(4096, 63);
 pub const CELL_TYPE_16K: StorageCellType = StorageCellType(16384, 63);
 pub const CELL_TYPE_64K: StorageCellType = StorageCellType(65536, 63);
+pub const CELL_TYPE_CREATIVE: StorageCellType = StorageCellType(i32::MAX, 63);
+
+/// Amount reported for items that never run out
+pub const INFINITE_COUNT: i32 = i32::MAX;
 
 #[derive(Ord, PartialOrd, Eq, PartialEq, Debug, Serialize)]
 pub struct StoredItem<'a, T: StoredItemType> {
@@ -44,8 +59,8 @@ pub struct StoredItem<'a, T: StoredItemType> {
 impl<'a, T: StoredItemType> Clone for StoredItem<'a, T> {
     fn clone(&self) -> Self {
         StoredItem {
-            item: self.item.clone(),
-            count: self.count.clone()
+            item: self.item,
+            count: self.count
         }
     }
 }
@@ -73,14 +88,56 @@ impl<T: StoredItemType> Add for StoredItem<'_, T> {
     }
 }
 
+#[derive(Debug, PartialEq, Clone, Copy, Default, Ord, PartialOrd, Eq, Serialize)]
+pub enum AccessMode {
+    #[default]
+    ReadWrite,
+    /// Extraction only
+    Read,
+    /// Insertion only
+    Write
+}
+
+impl AccessMode {
+    pub fn can_insert(&self) -> bool {
+        *self != AccessMode::Read
+    }
+
+    pub fn can_extract(&self) -> bool {
+        *self != AccessMode::Write
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Default, Ord, PartialOrd, Eq, Serialize)]
 pub struct StorageCellConfig {
-    pub priority: i32
+    pub priority: i32,
+    pub access: AccessMode
+}
+
+/// Process-wide unique cell identifier, stays valid across grid sorts and moves between grids
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct CellId(usize);
+
+impl CellId {
+    fn next() -> Self {
+        CellId(NEXT_CELL_ID.fetch_add(1, AtomicOrdering::Relaxed))
+    }
 }
 
-#[derive(Debug, Clone, Eq, Ord, Serialize)]
+#[derive(Debug, PartialEq, Clone, Copy, Ord, PartialOrd, Eq, Serialize)]
+pub enum StorageCellKind {
+    Standard,
+    /// Holds an unlimited amount of its partitioned items, voids insertions of them
+    Creative
+}
+
+#[derive(Debug, Clone, Eq, Serialize)]
 pub struct StorageCell<'a, T: StoredItemType> {
+    pub id: CellId,
     pub config: StorageCellConfig,
+    pub kind: StorageCellKind,
+    /// Items this cell accepts, an empty partition accepts everything unless the cell is creative
+    pub partition: BTreeSet<&'a T>,
     pub stored_types: i32,
     pub bytes_used: i32,
     pub stored_items: BTreeMap<&'a T, StoredItem<'a, T>>,
@@ -96,26 +153,39 @@ impl<'a, T: StoredItemType> PartialEq<Self> for StorageCell<'a, T> {
 
 impl<'a, T: StoredItemType> PartialOrd for StorageCell<'a, T> {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.config.priority.partial_cmp(&other.config.priority)
+        Some(self.cmp(other))
+    }
+}
+
+impl<'a, T: StoredItemType> Ord for StorageCell<'a, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.config.priority.cmp(&other.config.priority)
     }
 }
 
 impl<'a, T: StoredItemType> StorageCell<'a, T> {
     pub fn clear(&mut self) {
         *self = StorageCell {
-            cell_type: self.cell_type.clone(),
+            id: self.id,
+            cell_type: self.cell_type,
             config: self.config.clone(),
+            kind: self.kind,
+            partition: self.partition.clone(),
             stored_types: 0,
             bytes_used: 0,
             stored_items: Default::default(),
             stored_items_count: 0
+        };
+        if self.kind == StorageCellKind::Creative {
+            self.stored_items = self.partition.iter().map(|x| (*x, StoredItem::new(*x, INFINITE_COUNT))).collect();
+            self.refresh_cache();
         }
     }
 
     pub fn calc_stored_bytes(cell_type: &StorageCellType, stored_items: &BTreeMap<&T, StoredItem<T>>) -> i32 {
         let bytes_per_type = cell_type.get_bytes_per_type();
         let mut bytes: i32 = bytes_per_type * stored_items.keys().count() as i32;
-        for (_, stored_item) in stored_items {
+        for stored_item in stored_items.values() {
             bytes += (stored_item.count as f32 / 8.0f32).ceil() as i32
         }
         bytes
@@ -128,7 +198,10 @@ impl<'a, T: StoredItemType> StorageCell<'a, T> {
 
     pub fn new(cell_type: &'static StorageCellType) -> Self {
         StorageCell {
+            id: CellId::next(),
             config: Default::default(),
+            kind: StorageCellKind::Standard,
+            partition: BTreeSet::default(),
             stored_types: 0,
             bytes_used: 0,
             stored_items: BTreeMap::default(),
@@ -137,30 +210,57 @@ impl<'a, T: StoredItemType> StorageCell<'a, T> {
         }
     }
 
+    /// Creative cell reporting an unlimited amount of every partitioned item
+    pub fn creative(partition: Vec<&'a T>) -> Self {
+        let mut cell = StorageCell::new(&CELL_TYPE_CREATIVE);
+        cell.kind = StorageCellKind::Creative;
+        cell.partition = partition.into_iter().collect();
+        cell.clear();
+        cell
+    }
+
+    /// Creative cells only ever accept their partition, even when it is empty
+    pub fn accepts(&self, item: &T) -> bool {
+        match self.kind {
+            StorageCellKind::Standard => self.partition.is_empty() || self.partition.contains(item),
+            StorageCellKind::Creative => self.partition.contains(item)
+        }
+    }
+
     pub fn get_free_bytes(&self) -> i32 {
         self.cell_type.0 - self.bytes_used
     }
 
     pub fn get_free_space(&self, item: &StoredItem<T>) -> i32 {
+        if !self.accepts(item.item) {
+            return 0;
+        }
+        if self.kind == StorageCellKind::Creative {
+            return item.count;
+        }
         let bytes_per_type = self.cell_type.get_bytes_per_type();
         let stored_items = &self.stored_items;
         if stored_items.contains_key(item.item) {
             let stored_item = stored_items.get(item.item).unwrap();
-            return min(stored_item.count + item.count, Self::calc_free_space(stored_item, self.get_free_bytes()));
+            min(stored_item.count + item.count, Self::calc_free_space(stored_item, self.get_free_bytes()))
         } else {
             if self.bytes_used + bytes_per_type + 1 >= self.cell_type.0 || self.stored_types >= self.cell_type.1 {
                 return 0
             }
             let free_space = Self::calc_free_space(item, self.get_free_bytes() - self.cell_type.get_bytes_per_type());
-            return min(item.count, free_space);
+            min(item.count, free_space)
         }
     }
 
     pub fn is_full(&self) -> bool {
-        return self.bytes_used == self.cell_type.0;
+        self.kind == StorageCellKind::Standard && self.bytes_used == self.cell_type.0
     }
 
     pub fn insert(&mut self, item: StoredItem<'a, T>) -> i32 {
+        if self.kind == StorageCellKind::Creative {
+            // Voided
+            return self.get_free_space(&item);
+        }
         if self.is_full() {
             // Cell is full, nothing happens
             return 0;
@@ -170,7 +270,7 @@ impl<'a, T: StoredItemType> StorageCell<'a, T> {
         if count > 0 {
             let count = min(count, item.count);
             if self.stored_items.contains_key(item.item) {
-                let mut stored_item = self.stored_items.get_mut(item.item).unwrap();
+                let stored_item = self.stored_items.get_mut(item.item).unwrap();
                 stored_item.count += count;
                 transactions.push(Transactions::Insert(count));
             } else {
@@ -190,8 +290,11 @@ impl<'a, T: StoredItemType> StorageCell<'a, T> {
 
     pub fn refresh_cache(&mut self) {
         self.stored_types = self.stored_items.keys().count() as i32;
-        self.bytes_used = Self::calc_stored_bytes(self.cell_type, &self.stored_items);
-        self.stored_items_count = self.stored_items.values().map(|x| x.count).sum();
+        self.bytes_used = match self.kind {
+            StorageCellKind::Standard => Self::calc_stored_bytes(self.cell_type, &self.stored_items),
+            StorageCellKind::Creative => 0
+        };
+        self.stored_items_count = self.stored_items.values().fold(0, |acc, x| acc.saturating_add(x.count));
     }
 
     pub fn insert_many(&mut self, items: Iter<StoredItem<'a, T>>) -> Vec<i32> {
@@ -207,6 +310,10 @@ impl<'a, T: StoredItemType> StorageCell<'a, T> {
     }
 
     pub fn take(&mut self, item: &StoredItem<'a, T>) -> i32 {
+        if self.kind == StorageCellKind::Creative {
+            // Never depletes
+            return if self.stored_items.contains_key(item.item) { item.count } else { 0 };
+        }
         if self.stored_items.contains_key(item.item) {
             let stored_item = self.stored_items.get_mut(item.item).unwrap();
             let count = min(stored_item.count, item.count);
@@ -0,0 +1,312 @@
+use crate::grid::Grid;
+use crate::registry::{Registry, RegistryKey};
+use crate::storage::{StorageCell, StorageCellKind, StoredItem, StoredItemType};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use serde::{Serialize, Serializer};
+
+/// `namespace:path`, the namespace defaults to `minecraft`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TagName {
+    pub namespace: String,
+    pub path: String,
+}
+
+impl TagName {
+    pub fn new(namespace: &str, path: &str) -> Self {
+        TagName {
+            namespace: namespace.to_string(),
+            path: path.to_string()
+        }
+    }
+
+    /// Accepts `ns:path`, `path` and either with a leading `#`
+    pub fn parse(name: &str) -> Result<Self, TagError> {
+        let trimmed = name.strip_prefix('#').unwrap_or(name);
+        let (namespace, path) = trimmed.split_once(':').unwrap_or(("minecraft", trimmed));
+        let valid_namespace = !namespace.is_empty() && namespace.chars().all(|x| matches!(x, 'a'..='z' | '0'..='9' | '_' | '.' | '-'));
+        let valid_path = !path.is_empty() && path.chars().all(|x| matches!(x, 'a'..='z' | '0'..='9' | '_' | '.' | '-' | '/'));
+        if !valid_namespace || !valid_path {
+            return Err(TagError::InvalidName(name.to_string()));
+        }
+        Ok(TagName::new(namespace, path))
+    }
+}
+
+impl fmt::Display for TagName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.path)
+    }
+}
+
+impl Serialize for TagName {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        serializer.collect_str(self)
+    }
+}
+
+impl FromStr for TagName {
+    type Err = TagError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TagName::parse(s)
+    }
+}
+
+/// One value of a tag definition
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum TagEntry {
+    /// Id of an item or fluid
+    Key(String),
+    /// Every member of another tag, written `#ns:path`
+    Tag(TagName),
+    /// Skipped instead of failing when the id is not registered or the tag not defined
+    Optional(Box<TagEntry>)
+}
+
+impl TagEntry {
+    pub fn parse(value: &str) -> Result<Self, TagError> {
+        if value.starts_with('#') {
+            return TagName::parse(value).map(TagEntry::Tag);
+        }
+        Ok(TagEntry::Key(value.to_string()))
+    }
+
+    pub fn optional(self) -> Self {
+        match self {
+            TagEntry::Optional(_) => self,
+            _ => TagEntry::Optional(Box::new(self))
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum TagError {
+    InvalidName(String),
+    UnknownTag(TagName),
+    /// The id is not registered
+    UnknownKey { tag: TagName, key: String },
+    /// Tags referencing each other, the first tag is repeated at the end
+    Cyclic(Vec<TagName>)
+}
+
+/// Named group of interchangeable keys, e.g. `#minecraft:planks`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Tag<'a, T: StoredItemType> {
+    pub name: TagName,
+    members: BTreeSet<&'a T>,
+}
+
+impl<'a, T: StoredItemType> Tag<'a, T> {
+    pub fn new(name: TagName) -> Self {
+        Tag {
+            name,
+            members: BTreeSet::new()
+        }
+    }
+
+    pub fn with_members(name: TagName, members: Vec<&'a T>) -> Self {
+        Tag {
+            name,
+            members: members.into_iter().collect()
+        }
+    }
+
+    /// Returns false if `item` already was a member
+    pub fn insert(&mut self, item: &'a T) -> bool {
+        self.members.insert(item)
+    }
+
+    pub fn remove(&mut self, item: &T) -> bool {
+        self.members.remove(item)
+    }
+
+    pub fn contains(&self, item: &T) -> bool {
+        self.members.contains(item)
+    }
+
+    /// In key order
+    pub fn members(&self) -> impl Iterator<Item = &'a T> + '_ {
+        self.members.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+}
+
+/// Where tags look up the keys their ids stand for
+pub trait KeyLookup<T> {
+    fn lookup(&self, id: &str) -> Option<&T>;
+}
+
+impl<T> KeyLookup<T> for HashMap<String, T> {
+    fn lookup(&self, id: &str) -> Option<&T> {
+        self.get(id)
+    }
+}
+
+impl<T: RegistryKey> KeyLookup<T> for Registry<T> {
+    fn lookup(&self, id: &str) -> Option<&T> {
+        self.get_by_name(id)
+    }
+}
+
+/// Tag definitions for one kind of key, e.g. all item tags or all fluid tags
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TagRegistry {
+    definitions: BTreeMap<TagName, Vec<TagEntry>>,
+}
+
+impl TagRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces any earlier definition of `name`
+    pub fn define(&mut self, name: TagName, entries: Vec<TagEntry>) {
+        self.definitions.insert(name, entries);
+    }
+
+    /// Adds to the definition of `name`, creating it if needed
+    pub fn extend(&mut self, name: TagName, entries: Vec<TagEntry>) {
+        self.definitions.entry(name).or_default().extend(entries);
+    }
+
+    pub fn get(&self, name: &TagName) -> Option<&[TagEntry]> {
+        self.definitions.get(name).map(|x| x.as_slice())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &TagName> {
+        self.definitions.keys()
+    }
+
+    /// Ids of every member of `name`, nested tags included
+    pub fn member_ids(&self, name: &TagName) -> Result<BTreeSet<String>, TagError> {
+        Ok(self.members_required(name)?.into_keys().collect())
+    }
+
+    /// Member ids mapped to whether they have to be registered
+    fn members_required(&self, name: &TagName) -> Result<BTreeMap<String, bool>, TagError> {
+        let mut ids = BTreeMap::new();
+        self.collect(name, true, &mut vec![], &mut ids)?;
+        Ok(ids)
+    }
+
+    fn collect(&self, name: &TagName, required: bool, resolving: &mut Vec<TagName>, ids: &mut BTreeMap<String, bool>) -> Result<(), TagError> {
+        if let Some(start) = resolving.iter().position(|x| x == name) {
+            let mut cycle = resolving[start..].to_vec();
+            cycle.push(name.clone());
+            return Err(TagError::Cyclic(cycle));
+        }
+        let entries = match self.definitions.get(name) {
+            Some(entries) => entries,
+            None if !required => return Ok(()),
+            None => return Err(TagError::UnknownTag(name.clone()))
+        };
+        resolving.push(name.clone());
+        for entry in entries {
+            self.collect_entry(entry, required, resolving, ids)?;
+        }
+        resolving.pop();
+        Ok(())
+    }
+
+    fn collect_entry(&self, entry: &TagEntry, required: bool, resolving: &mut Vec<TagName>, ids: &mut BTreeMap<String, bool>) -> Result<(), TagError> {
+        match entry {
+            TagEntry::Key(id) => {
+                *ids.entry(id.clone()).or_default() |= required;
+                Ok(())
+            }
+            TagEntry::Tag(nested) => self.collect(nested, required, resolving, ids),
+            TagEntry::Optional(entry) => self.collect_entry(entry, false, resolving, ids)
+        }
+    }
+
+    pub fn contains(&self, name: &TagName, id: &str) -> Result<bool, TagError> {
+        Ok(self.member_ids(name)?.contains(id))
+    }
+
+    /// Every tag `id` is a member of, tags that fail to resolve are skipped
+    pub fn tags_of(&self, id: &str) -> Vec<TagName> {
+        self.names().filter(|x| self.contains(x, id).unwrap_or(false)).cloned().collect()
+    }
+
+    /// Members of `name` as keys from `keys`, every id not marked optional has to be registered
+    pub fn resolve<'a, T: StoredItemType, K: KeyLookup<T>>(&self, name: &TagName, keys: &'a K) -> Result<Tag<'a, T>, TagError> {
+        let mut tag = Tag::new(name.clone());
+        for (id, required) in self.members_required(name)? {
+            match keys.lookup(&id) {
+                Some(key) => {
+                    tag.insert(key);
+                }
+                None if !required => {}
+                None => return Err(TagError::UnknownKey { tag: name.clone(), key: id })
+            }
+        }
+        Ok(tag)
+    }
+
+    /// Every required reference that does not resolve against `keys` or the other tags, and every loop
+    pub fn check<T, K: KeyLookup<T>>(&self, keys: &K) -> TagReport {
+        let mut report = TagReport::default();
+        for (name, entries) in self.definitions.iter() {
+            for entry in entries {
+                match entry {
+                    TagEntry::Key(id) if keys.lookup(id).is_none() => report.unresolved.push((name.clone(), id.clone())),
+                    TagEntry::Tag(nested) if !self.definitions.contains_key(nested) => report.unresolved.push((name.clone(), format!("#{}", nested))),
+                    _ => {}
+                }
+            }
+            if let Err(TagError::Cyclic(mut cycle)) = self.member_ids(name) {
+                // Start every loop at its smallest name so it is reported once
+                cycle.pop();
+                let start = (0..cycle.len()).min_by_key(|x| &cycle[*x]).unwrap();
+                cycle.rotate_left(start);
+                cycle.push(cycle[0].clone());
+                if !report.cyclic.contains(&cycle) {
+                    report.cyclic.push(cycle);
+                }
+            }
+        }
+        report
+    }
+}
+
+/// Problems found by `TagRegistry::check`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TagReport {
+    /// Tag and the id or `#tag` in it that does not resolve
+    pub unresolved: Vec<(TagName, String)>,
+    /// Tags referencing each other, the first tag is repeated at the end
+    pub cyclic: Vec<Vec<TagName>>,
+}
+
+impl TagReport {
+    pub fn is_ok(&self) -> bool {
+        self.unresolved.is_empty() && self.cyclic.is_empty()
+    }
+}
+
+impl<'a, T: StoredItemType> Grid<'a, T> {
+    /// Cached stacks of every stored member of `tag`, in key order
+    pub fn search_tag(&self, tag: &Tag<'a, T>) -> Vec<StoredItem<'a, T>> {
+        tag.members().filter_map(|x| self.stored_items_cache.get(x).cloned()).collect()
+    }
+}
+
+impl<'a, T: StoredItemType> StorageCell<'a, T> {
+    /// Partitions the cell to the members of `tag`, replacing the previous partition.
+    /// A creative cell is restocked with the new members.
+    pub fn partition_to(&mut self, tag: &Tag<'a, T>) {
+        self.partition = tag.members().collect();
+        if self.kind == StorageCellKind::Creative {
+            self.clear();
+        }
+    }
+}
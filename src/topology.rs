@@ -0,0 +1,274 @@
+use crate::drive::{CellHost, CellHostKind, HostId};
+use crate::grid::{GridId, GridNetwork};
+use crate::item::Item;
+use crate::registry::KeyRegistry;
+use crate::channel::{self, ChannelAllocation};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use serde::Serialize;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct BlockPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl BlockPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        BlockPos { x, y, z }
+    }
+
+    /// Face-adjacent positions
+    pub fn neighbours(&self) -> [BlockPos; 6] {
+        let BlockPos { x, y, z } = *self;
+        [
+            BlockPos::new(x + 1, y, z),
+            BlockPos::new(x - 1, y, z),
+            BlockPos::new(x, y + 1, z),
+            BlockPos::new(x, y - 1, z),
+            BlockPos::new(x, y, z + 1),
+            BlockPos::new(x, y, z - 1),
+        ]
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct NodeId(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum CableKind {
+    Normal,
+    Dense
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum DeviceKind {
+    Controller,
+    Cable(CableKind),
+    Drive,
+    Chest,
+    Terminal,
+    ImportBus,
+    ExportBus,
+    StorageBus,
+    Interface
+}
+
+/// Block placed in the world, connected to every node on an adjacent face
+#[derive(Debug, Clone, Serialize)]
+pub struct Node {
+    pub id: NodeId,
+    pub kind: DeviceKind,
+    pub pos: BlockPos,
+    /// Drive or chest backing this node
+    pub host: Option<HostId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopologyError {
+    Occupied(BlockPos),
+    /// Drives and chests have to be placed through `add_host`
+    HostRequired(DeviceKind)
+}
+
+/// Physical layout of one or more ME networks. Every connected component owns exactly one `GridNetwork`.
+#[derive(Debug, Default)]
+pub struct Topology<'a> {
+    pub nodes: BTreeMap<NodeId, Node>,
+    pub positions: BTreeMap<BlockPos, NodeId>,
+    pub networks: BTreeMap<GridId, GridNetwork<'a>>,
+    pub membership: BTreeMap<NodeId, GridId>,
+    /// Kept up to date on every placement and removal
+    pub channels: ChannelAllocation,
+    /// Handed to every network created from now on
+    pub keys: Option<&'a KeyRegistry>,
+    next_node_id: usize,
+}
+
+impl<'a> Topology<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn node_at(&self, pos: BlockPos) -> Option<&Node> {
+        self.positions.get(&pos).and_then(|x| self.nodes.get(x))
+    }
+
+    pub fn neighbours(&self, id: NodeId) -> Vec<NodeId> {
+        match self.nodes.get(&id) {
+            Some(node) => node.pos.neighbours().iter().filter_map(|x| self.positions.get(x).copied()).collect(),
+            None => vec![]
+        }
+    }
+
+    pub fn network_of(&self, id: NodeId) -> Option<&GridNetwork<'a>> {
+        self.networks.get(self.membership.get(&id)?)
+    }
+
+    pub fn network_of_mut(&mut self, id: NodeId) -> Option<&mut GridNetwork<'a>> {
+        let grid_id = *self.membership.get(&id)?;
+        self.networks.get_mut(&grid_id)
+    }
+
+    /// Nodes of the network a node belongs to
+    pub fn component(&self, id: NodeId) -> Vec<NodeId> {
+        match self.membership.get(&id) {
+            Some(grid_id) => self.membership.iter().filter(|x| x.1 == grid_id).map(|x| *x.0).collect(),
+            None => vec![]
+        }
+    }
+
+    pub fn add_node(&mut self, pos: BlockPos, kind: DeviceKind) -> Result<NodeId, TopologyError> {
+        let id = match kind {
+            DeviceKind::Drive | DeviceKind::Chest => return Err(TopologyError::HostRequired(kind)),
+            _ => self.place(pos, kind, None)?
+        };
+        self.assign_channels();
+        Ok(id)
+    }
+
+    /// Whether a device can work, devices needing a channel are offline without one
+    pub fn is_online(&self, id: NodeId) -> bool {
+        match self.nodes.get(&id) {
+            Some(node) => !node.kind.requires_channel() || self.channels.online.contains(&id),
+            None => false
+        }
+    }
+
+    /// Recomputes channels and takes drives and chests without one out of their grid
+    pub fn assign_channels(&mut self) -> &ChannelAllocation {
+        self.channels = channel::allocate(self);
+        let hosts: Vec<(NodeId, HostId)> = self.nodes.values().filter_map(|x| x.host.map(|host| (x.id, host))).collect();
+        for (node_id, host_id) in hosts {
+            let has_channel = self.channels.online.contains(&node_id);
+            let network = match self.network_of_mut(node_id) {
+                Some(network) => network,
+                None => continue
+            };
+            if network.item_grid.hosts.get(&host_id).is_some_and(|x| x.has_channel != has_channel) {
+                network.item_grid.update_host(host_id, |x| x.has_channel = has_channel);
+            }
+        }
+        &self.channels
+    }
+
+    /// Pays one tick of idle drain on every network, returns the ones that went offline
+    pub fn tick_energy(&mut self) -> Vec<GridId> {
+        let mut device_drain: BTreeMap<GridId, f64> = BTreeMap::new();
+        for (node_id, grid_id) in self.membership.iter() {
+            *device_drain.entry(*grid_id).or_default() += self.nodes[node_id].kind.idle_drain();
+        }
+        let mut offline = vec![];
+        for (grid_id, network) in self.networks.iter_mut() {
+            if !network.tick_energy(device_drain.get(grid_id).copied().unwrap_or(0.0)) {
+                offline.push(*grid_id);
+            }
+        }
+        offline
+    }
+
+    /// Places a drive or chest, its cells join the network it connects to
+    pub fn add_host(&mut self, pos: BlockPos, host: CellHost<'a, Item>) -> Result<NodeId, TopologyError> {
+        let kind = match host.kind {
+            CellHostKind::Drive => DeviceKind::Drive,
+            CellHostKind::Chest => DeviceKind::Chest
+        };
+        let id = self.place(pos, kind, Some(host.id))?;
+        if let Some(network) = self.network_of_mut(id) {
+            network.item_grid.add_host(host);
+        }
+        self.assign_channels();
+        Ok(id)
+    }
+
+    fn place(&mut self, pos: BlockPos, kind: DeviceKind, host: Option<HostId>) -> Result<NodeId, TopologyError> {
+        if self.positions.contains_key(&pos) {
+            return Err(TopologyError::Occupied(pos));
+        }
+        let id = NodeId(self.next_node_id);
+        self.next_node_id += 1;
+        self.nodes.insert(id, Node { id, kind, pos, host });
+        self.positions.insert(pos, id);
+
+        let mut touching: Vec<GridId> = self.neighbours(id).iter().filter_map(|x| self.membership.get(x).copied()).collect();
+        touching.sort();
+        touching.dedup();
+        let grid_id = match touching.split_first() {
+            Some((first, rest)) => {
+                // Bridging several networks merges them into the first one
+                for other_id in rest {
+                    if let Some(other) = self.networks.remove(other_id) {
+                        self.networks.get_mut(first).unwrap().union(other);
+                    }
+                    for membership in self.membership.values_mut().filter(|x| *x == other_id) {
+                        *membership = *first;
+                    }
+                }
+                *first
+            }
+            None => {
+                let network = GridNetwork { keys: self.keys, ..Default::default() };
+                let grid_id = network.id();
+                self.networks.insert(grid_id, network);
+                grid_id
+            }
+        };
+        self.membership.insert(id, grid_id);
+        Ok(id)
+    }
+
+    /// Removes a node, splitting its network if it was the only link between parts.
+    /// A removed drive or chest is handed back with its cells.
+    pub fn remove_node(&mut self, id: NodeId) -> Option<(Node, Option<CellHost<'a, Item>>)> {
+        let neighbours = self.neighbours(id);
+        let node = self.nodes.remove(&id)?;
+        self.positions.remove(&node.pos);
+        let grid_id = self.membership.remove(&id)?;
+        let host = match node.host {
+            Some(host_id) => self.networks.get_mut(&grid_id).and_then(|x| x.item_grid.remove_host(host_id)),
+            None => None
+        };
+
+        let mut seen = BTreeSet::new();
+        let mut parts = vec![];
+        for start in neighbours {
+            if seen.contains(&start) {
+                continue;
+            }
+            let part = self.flood(start);
+            seen.extend(part.iter().copied());
+            parts.push(part);
+        }
+        if parts.is_empty() {
+            self.networks.remove(&grid_id);
+        }
+        // The first part keeps the network, the others are split off with their drives
+        for part in parts.into_iter().skip(1) {
+            let hosts: BTreeSet<HostId> = part.iter().filter_map(|x| self.nodes.get(x).and_then(|x| x.host)).collect();
+            let network = self.networks.get_mut(&grid_id).unwrap().split_off_hosts(&hosts);
+            let new_id = network.id();
+            self.networks.insert(new_id, network);
+            for node_id in part {
+                self.membership.insert(node_id, new_id);
+            }
+        }
+        self.assign_channels();
+        Some((node, host))
+    }
+
+    /// Every node reachable from `start`
+    fn flood(&self, start: NodeId) -> BTreeSet<NodeId> {
+        let mut visited = BTreeSet::new();
+        let mut queue = VecDeque::new();
+        visited.insert(start);
+        queue.push_back(start);
+        while let Some(id) = queue.pop_front() {
+            for next in self.neighbours(id) {
+                if visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        visited
+    }
+}
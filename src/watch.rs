@@ -0,0 +1,111 @@
+use crate::storage::StoredItemType;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::sync::mpsc::Sender;
+use serde::Serialize;
+
+/// Cached amount of a key going from `old` to `new`.
+/// Owns a copy of the key so that sinks never borrow from the grid's key store.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoredItemChange<T: StoredItemType> {
+    pub item: T,
+    pub old: i32,
+    pub new: i32,
+}
+
+pub enum WatchFilter<'a, T: StoredItemType> {
+    All,
+    Keys(BTreeSet<&'a T>)
+}
+
+impl<'a, T: StoredItemType> WatchFilter<'a, T> {
+    pub fn key(item: &'a T) -> Self {
+        WatchFilter::Keys(vec![item].into_iter().collect())
+    }
+
+    pub fn matches(&self, item: &T) -> bool {
+        match self {
+            WatchFilter::All => true,
+            WatchFilter::Keys(keys) => keys.contains(item)
+        }
+    }
+}
+
+pub type WatchCallback<T> = Box<dyn FnMut(&StoredItemChange<T>)>;
+
+pub enum WatchSink<T: StoredItemType> {
+    Callback(WatchCallback<T>),
+    /// Dropped once the receiving end hangs up
+    Channel(Sender<StoredItemChange<T>>)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct WatcherId(usize);
+
+struct Watcher<'a, T: StoredItemType> {
+    id: WatcherId,
+    filter: WatchFilter<'a, T>,
+    sink: WatchSink<T>,
+}
+
+pub struct Watchers<'a, T: StoredItemType> {
+    watchers: Vec<Watcher<'a, T>>,
+    next_id: usize,
+}
+
+impl<'a, T: StoredItemType> Default for Watchers<'a, T> {
+    fn default() -> Self {
+        Watchers {
+            watchers: vec![],
+            next_id: 0
+        }
+    }
+}
+
+impl<'a, T: StoredItemType> fmt::Debug for Watchers<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watchers").field("count", &self.watchers.len()).finish()
+    }
+}
+
+impl<'a, T: StoredItemType> Watchers<'a, T> {
+    pub fn is_empty(&self) -> bool {
+        self.watchers.is_empty()
+    }
+
+    pub fn add(&mut self, filter: WatchFilter<'a, T>, sink: WatchSink<T>) -> WatcherId {
+        let id = WatcherId(self.next_id);
+        self.next_id += 1;
+        self.watchers.push(Watcher { id, filter, sink });
+        id
+    }
+
+    pub fn remove(&mut self, id: WatcherId) -> bool {
+        let len = self.watchers.len();
+        self.watchers.retain(|x| x.id != id);
+        self.watchers.len() != len
+    }
+
+    pub fn notify(&mut self, item: &T, old: i32, new: i32) {
+        if old == new || !self.watchers.iter().any(|x| x.filter.matches(item)) {
+            return;
+        }
+        let change = StoredItemChange {
+            item: item.clone(),
+            old,
+            new
+        };
+        self.watchers.retain_mut(|watcher| {
+            if !watcher.filter.matches(&change.item) {
+                return true;
+            }
+            match &mut watcher.sink {
+                WatchSink::Callback(callback) => {
+                    callback(&change);
+                    true
+                }
+                WatchSink::Channel(sender) => sender.send(change.clone()).is_ok()
+            }
+        });
+    }
+}